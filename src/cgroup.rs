@@ -0,0 +1,275 @@
+/*
+Reads the cgroup v2 files of the cgroup run-info is running in
+Inside a container /proc/stat and /proc/meminfo describe the host, these files describe the container itself
+*/
+
+use std::env;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+use time;
+
+use fsutils::{read_file, read_keyed};
+
+pub const CGROUP_ROOT: &'static str = "/sys/fs/cgroup";
+
+pub struct CGroupInfo {
+	path: PathBuf,
+
+	pub cpu_limit: Option<f64>, //in cores, None if unlimited
+	pub cpu_usage: f64, //share of the limit used since the last update
+	pub throttled_usec: u64, //since the last update
+	pub throttled_periods: u64,
+	pub periods: u64,
+
+	pub memory_current: u64,
+	pub memory_max: Option<u64>,
+	pub swap_current: u64,
+	pub swap_max: Option<u64>,
+
+	//memory.events counters since the cgroup was created
+	pub events_high: u64,
+	pub events_max: u64,
+	pub events_oom: u64,
+	pub events_oom_kill: u64,
+
+	//raw counters of the last update, to calculate the deltas
+	last_usage_usec: u64,
+	last_throttled_usec: u64,
+	last_throttled_periods: u64,
+	last_periods: u64,
+	last_update_ns: u64
+}
+
+impl CGroupInfo {
+	//returns the cgroup run-info belongs to, if the system uses cgroup v2
+	//root is usually CGROUP_ROOT, but can point to any tree with the same layout
+	pub fn detect<P: AsRef<Path>>(root: P) -> Option<CGroupInfo> {
+		let root = root.as_ref();
+		if !root.join("cgroup.controllers").exists() {
+			return None;
+		}
+		//the v2 entry looks like "0::/docker/<id>", with a cgroup namespace it is just "0::/"
+		let own = match read_file("/proc/self/cgroup") {
+			Ok(v) => v,
+			Err(_) => return None
+		};
+		let relative = own.lines()
+			.filter(|line| line.starts_with("0::"))
+			.map(|line| line[3..].trim_start_matches('/').to_owned())
+			.next()
+			.unwrap_or(String::new());
+		let mut path = root.join(relative);
+		if !path.join("cgroup.controllers").exists() { //the host hierarchy is not mounted
+			path = root.to_path_buf();
+		}
+		Some(CGroupInfo::new(path))
+	}
+
+	pub fn new(path: PathBuf) -> CGroupInfo {
+		CGroupInfo {
+			path: path,
+			cpu_limit: None,
+			cpu_usage: 0.0,
+			throttled_usec: 0,
+			throttled_periods: 0,
+			periods: 0,
+			memory_current: 0,
+			memory_max: None,
+			swap_current: 0,
+			swap_max: None,
+			events_high: 0,
+			events_max: 0,
+			events_oom: 0,
+			events_oom_kill: 0,
+			last_usage_usec: 0,
+			last_throttled_usec: 0,
+			last_throttled_periods: 0,
+			last_periods: 0,
+			last_update_ns: 0
+		}
+	}
+
+	//host_cores is used as the cpu limit if the cgroup has none
+	pub fn update(&mut self, host_cores: usize) -> Result<()> {
+		self.update_at(host_cores, time::precise_time_ns())
+	}
+
+	fn update_at(&mut self, host_cores: usize, now_ns: u64) -> Result<()> {
+		let cpu_stat = try!(read_keyed(self.path.join("cpu.stat")));
+		let usage_usec = cpu_stat.get("usage_usec").cloned().unwrap_or(0);
+		let throttled_usec = cpu_stat.get("throttled_usec").cloned().unwrap_or(0);
+		let throttled_periods = cpu_stat.get("nr_throttled").cloned().unwrap_or(0);
+		let periods = cpu_stat.get("nr_periods").cloned().unwrap_or(0);
+
+		self.cpu_limit = read_file(self.path.join("cpu.max")).ok().and_then(|plain| parse_cpu_max(&plain));
+
+		if self.last_update_ns != 0 {
+			let elapsed_usec = now_ns.saturating_sub(self.last_update_ns) as f64 / 1000.0;
+			let limit = self.cpu_limit.unwrap_or(host_cores as f64);
+			self.cpu_usage = 0.0;
+			if elapsed_usec > 0.0 && limit > 0.0 {
+				let used = usage_usec.saturating_sub(self.last_usage_usec) as f64 / (elapsed_usec * limit);
+				self.cpu_usage = used.min(1.0);
+			}
+			self.throttled_usec = throttled_usec.saturating_sub(self.last_throttled_usec);
+			self.throttled_periods = throttled_periods.saturating_sub(self.last_throttled_periods);
+			self.periods = periods.saturating_sub(self.last_periods);
+		}
+		self.last_usage_usec = usage_usec;
+		self.last_throttled_usec = throttled_usec;
+		self.last_throttled_periods = throttled_periods;
+		self.last_periods = periods;
+		self.last_update_ns = now_ns;
+
+		self.memory_current = try!(read_value(self.path.join("memory.current"))).unwrap_or(0);
+		self.memory_max = try!(read_value(self.path.join("memory.max")));
+		//the swap files are missing if swap accounting is disabled
+		self.swap_current = read_value(self.path.join("memory.swap.current")).ok().and_then(|v| v).unwrap_or(0);
+		self.swap_max = read_value(self.path.join("memory.swap.max")).ok().and_then(|v| v);
+
+		let events = try!(read_keyed(self.path.join("memory.events")));
+		self.events_high = events.get("high").cloned().unwrap_or(0);
+		self.events_max = events.get("max").cloned().unwrap_or(0);
+		self.events_oom = events.get("oom").cloned().unwrap_or(0);
+		self.events_oom_kill = events.get("oom_kill").cloned().unwrap_or(0);
+
+		Ok(())
+	}
+
+	//host_total is used as the limit if the cgroup has none (in bytes)
+	pub fn memory_use(&self, host_total: u64) -> f64 {
+		let limit = self.memory_max.unwrap_or(host_total);
+		if limit == 0 {
+			return 0.0;
+		}
+		(self.memory_current as f64 / limit as f64).min(1.0)
+	}
+}
+
+//true if there are signs of docker, podman or kubernetes
+pub fn in_container() -> bool {
+	Path::new("/.dockerenv").exists()
+		|| Path::new("/run/.containerenv").exists()
+		|| env::var_os("container").is_some()
+		|| env::var_os("KUBERNETES_SERVICE_HOST").is_some()
+}
+
+//"max 100000" or "<quota> <period>", in cores
+fn parse_cpu_max(plain: &str) -> Option<f64> {
+	let mut columns = plain.split_whitespace();
+	match (columns.next(), columns.next()) {
+		(Some(quota), Some(period)) => match (quota.parse::<f64>(), period.parse::<f64>()) {
+			(Ok(quota), Ok(period)) if period > 0.0 => Some(quota / period),
+			_ => None
+		},
+		_ => None
+	}
+}
+
+//reads files containing a single number or "max" (None)
+fn read_value(location: PathBuf) -> Result<Option<u64>> {
+	let plain = try!(read_file(location));
+	Ok(plain.trim().parse::<u64>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::fs::{self, File};
+	use std::io::Write;
+
+	//a cgroup directory with the given (file, content) pairs
+	fn fixture(test: &str, files: &[(&str, &str)]) -> PathBuf {
+		let root = env::temp_dir().join(format!("run-info-cgroup-{}-{}", test, ::std::process::id()));
+		let _ = fs::remove_dir_all(&root);
+		fs::create_dir_all(&root).unwrap();
+		write_files(&root, files);
+		root
+	}
+
+	fn write_files(root: &Path, files: &[(&str, &str)]) {
+		for &(file, content) in files {
+			File::create(root.join(file)).unwrap().write_all(content.as_bytes()).unwrap();
+		}
+	}
+
+	fn cpu_stat(usage: u64, throttled: u64, nr_throttled: u64, nr_periods: u64) -> String {
+		format!("usage_usec {}\nuser_usec 0\nsystem_usec 0\nnr_periods {}\nnr_throttled {}\nthrottled_usec {}\n",
+			usage, nr_periods, nr_throttled, throttled)
+	}
+
+	const MEMORY: [(&'static str, &'static str); 3] = [
+		("memory.current", "104857600\n"),
+		("memory.max", "max\n"),
+		("memory.events", "low 0\nhigh 2\nmax 5\noom 1\noom_kill 1\n")
+	];
+
+	#[test]
+	fn cpu_max() {
+		assert_eq!(parse_cpu_max("max 100000\n"), None);
+		assert_eq!(parse_cpu_max("50000 100000\n"), Some(0.5));
+		assert_eq!(parse_cpu_max("200000 100000"), Some(2.0));
+		assert_eq!(parse_cpu_max("50000 0"), None);
+		assert_eq!(parse_cpu_max(""), None);
+	}
+
+	#[test]
+	fn usage_and_throttling_are_deltas() {
+		let root = fixture("deltas", &MEMORY);
+		write_files(&root, &[("cpu.max", "50000 100000\n"), ("cpu.stat", &cpu_stat(1000000, 20000, 3, 100))]);
+		let mut cgroup = CGroupInfo::new(root.clone());
+		cgroup.update_at(8, 1000000000).unwrap();
+		//the first update has nothing to compare with
+		assert_eq!(cgroup.cpu_limit, Some(0.5));
+		assert_eq!((cgroup.cpu_usage, cgroup.throttled_usec, cgroup.periods), (0.0, 0, 0));
+
+		//250 ms of CPU time in one second, with a limit of half a core
+		write_files(&root, &[("cpu.stat", &cpu_stat(1250000, 70000, 8, 110))]);
+		cgroup.update_at(8, 2000000000).unwrap();
+		assert_eq!(cgroup.cpu_usage, 0.5);
+		assert_eq!(cgroup.throttled_usec, 50000);
+		assert_eq!(cgroup.throttled_periods, 5);
+		assert_eq!(cgroup.periods, 10);
+
+		//without a limit the host cores are the limit, a counter reset counts as no usage
+		write_files(&root, &[("cpu.max", "max 100000\n"), ("cpu.stat", &cpu_stat(1000, 0, 0, 0))]);
+		cgroup.update_at(8, 3000000000).unwrap();
+		assert_eq!(cgroup.cpu_limit, None);
+		assert_eq!((cgroup.cpu_usage, cgroup.throttled_usec, cgroup.periods), (0.0, 0, 0));
+		write_files(&root, &[("cpu.stat", &cpu_stat(2001000, 0, 0, 0))]);
+		cgroup.update_at(8, 4000000000).unwrap();
+		assert_eq!(cgroup.cpu_usage, 0.25);
+		let _ = fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn unlimited_memory() {
+		let root = fixture("memory", &MEMORY);
+		write_files(&root, &[("cpu.stat", &cpu_stat(0, 0, 0, 0))]);
+		let mut cgroup = CGroupInfo::new(root.clone());
+		cgroup.update_at(1, 1).unwrap();
+		assert_eq!(cgroup.memory_current, 104857600);
+		assert_eq!(cgroup.memory_max, None);
+		//without swap accounting
+		assert_eq!((cgroup.swap_current, cgroup.swap_max), (0, None));
+		assert_eq!((cgroup.events_high, cgroup.events_max, cgroup.events_oom, cgroup.events_oom_kill), (2, 5, 1, 1));
+		//the host memory is the limit
+		assert_eq!(cgroup.memory_use(419430400), 0.25);
+		write_files(&root, &[("memory.max", "209715200\n")]);
+		cgroup.update_at(1, 2).unwrap();
+		assert_eq!(cgroup.memory_max, Some(209715200));
+		assert_eq!(cgroup.memory_use(419430400), 0.5);
+		let _ = fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn missing_files_are_errors() {
+		let root = fixture("missing", &[("cpu.stat", &cpu_stat(0, 0, 0, 0))]);
+		assert!(CGroupInfo::new(root.clone()).update_at(1, 1).is_err());
+		//not a cgroup v2 tree
+		assert!(CGroupInfo::detect(&root).is_none());
+		let _ = fs::remove_dir_all(&root);
+	}
+}
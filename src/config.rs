@@ -0,0 +1,204 @@
+/*
+Reads the settings from ~/.config/run-info/config.toml, the command line arguments take precedence
+Keys of a [profiles.<name>] table replace the top-level ones if the profile is chosen with --profile
+*/
+
+use toml::{self, Value};
+
+use fsutils::read_file;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+//every key the file may contain, a typo should not be ignored silently
+const KEYS: [&'static str; 32] = [
+	"delay", "color", "graph", "mode", "zoom", "sections", "bar_width", "thresholds.warning", "thresholds.critical",
+	"filter", "json_summary", "format", "target", "prefix", "output", "rotate", "keep", "gzip", "timestamp", "utc",
+	"pid_file", "gradient", "theme", "color_depth", "colors.highlight", "colors.header", "colors.graph", "colors.red",
+	"colors.green", "colors.yellow", "layout.rows", "layout.graph_height"
+];
+
+pub struct Config {
+	values: BTreeMap<String, Value> //tables are flattened to "table.key"
+}
+
+impl Config {
+	//an empty config if the default file does not exist, but an explicitly given file must be there
+	pub fn load(path: Option<&str>, profile: Option<&str>) -> Result<Config, String> {
+		let (path, required) = match path {
+			Some(v) => (PathBuf::from(v), true),
+			None => match default_path() {
+				Some(v) => (v, false),
+				None => return Config::parse("", profile)
+			}
+		};
+		match read_file(&path) {
+			Ok(text) => Config::parse(&text, profile).map_err(|err| format!("{}: {}", path.display(), err)),
+			Err(_) if !required => Config::parse("", profile),
+			Err(err) => Err(format!("{}: {}", path.display(), err))
+		}
+	}
+
+	pub fn parse(text: &str, profile: Option<&str>) -> Result<Config, String> {
+		let mut root = match text.parse::<Value>() {
+			Ok(Value::Table(v)) => v,
+			Ok(_) => return Err(String::from("expected a table")),
+			Err(err) => return Err(format!("{}", err))
+		};
+		let profiles = root.remove("profiles");
+		let mut values = BTreeMap::new();
+		flatten(&mut values, "", root);
+		if let Some(name) = profile {
+			let table = match profiles.as_ref().and_then(|profiles| profiles.get(name)) {
+				Some(&Value::Table(ref v)) => v.clone(),
+				_ => return Err(format!("profile {} is not defined", name))
+			};
+			flatten(&mut values, "", table);
+		}
+		for key in values.keys() {
+			if !KEYS.contains(&key.as_str()) {
+				return Err(format!("unknown key {}", key));
+			}
+		}
+		Ok(Config {
+			values: values
+		})
+	}
+
+	//numbers and strings as they would be given on the command line
+	pub fn value(&self, key: &str) -> Result<Option<String>, String> {
+		match self.values.get(key) {
+			Some(&Value::String(ref v)) => Ok(Some(v.clone())),
+			Some(&Value::Integer(v)) => Ok(Some(format!("{}", v))),
+			Some(&Value::Float(v)) => Ok(Some(format!("{}", v))),
+			Some(_) => Err(format!("{} must be a string or a number", key)),
+			None => Ok(None)
+		}
+	}
+
+	pub fn flag(&self, key: &str) -> Result<Option<bool>, String> {
+		match self.values.get(key) {
+			Some(&Value::Boolean(v)) => Ok(Some(v)),
+			Some(_) => Err(format!("{} must be true or false", key)),
+			None => Ok(None)
+		}
+	}
+
+	pub fn number(&self, key: &str) -> Result<Option<f64>, String> {
+		match self.values.get(key) {
+			Some(&Value::Integer(v)) => Ok(Some(v as f64)),
+			Some(&Value::Float(v)) => Ok(Some(v)),
+			Some(_) => Err(format!("{} must be a number", key)),
+			None => Ok(None)
+		}
+	}
+
+	pub fn list(&self, key: &str) -> Result<Option<Vec<String>>, String> {
+		let array = match self.values.get(key) {
+			Some(&Value::Array(ref v)) => v,
+			Some(_) => return Err(format!("{} must be a list of strings", key)),
+			None => return Ok(None)
+		};
+		let mut result = Vec::new();
+		for value in array {
+			match *value {
+				Value::String(ref v) => result.push(v.clone()),
+				_ => return Err(format!("{} must be a list of strings", key))
+			}
+		}
+		Ok(Some(result))
+	}
+}
+
+//$XDG_CONFIG_HOME/run-info/config.toml, by default in ~/.config
+fn default_path() -> Option<PathBuf> {
+	let config_home = match env::var_os("XDG_CONFIG_HOME") {
+		Some(ref v) if !v.is_empty() => PathBuf::from(v),
+		_ => match env::var_os("HOME") {
+			Some(v) => PathBuf::from(v).join(".config"),
+			None => return None
+		}
+	};
+	Some(config_home.join("run-info").join("config.toml"))
+}
+
+fn flatten(values: &mut BTreeMap<String, Value>, prefix: &str, table: toml::value::Table) {
+	for (key, value) in table {
+		let key = format!("{}{}", prefix, key);
+		match value {
+			Value::Table(table) => flatten(values, &format!("{}.", key), table),
+			value => { values.insert(key, value); }
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CONFIG: &'static str = "
+delay = 1000
+color = false
+mode = \"small\"
+sections = [\"users\", \"pressure\"]
+
+[thresholds]
+warning = 60
+critical = 85.5
+
+[profiles.server]
+delay = 5000
+mode = \"log\"
+format = \"influx\"
+target = \"udp://127.0.0.1:8089\"
+
+[profiles.server.thresholds]
+critical = 95
+";
+
+	#[test]
+	fn top_level_values() {
+		let config = Config::parse(CONFIG, None).unwrap();
+		assert_eq!(config.value("delay").unwrap(), Some(String::from("1000")));
+		assert_eq!(config.value("mode").unwrap(), Some(String::from("small")));
+		assert_eq!(config.flag("color").unwrap(), Some(false));
+		assert_eq!(config.flag("graph").unwrap(), None);
+		assert_eq!(config.number("thresholds.critical").unwrap(), Some(85.5));
+		assert_eq!(config.list("sections").unwrap(), Some(vec![String::from("users"), String::from("pressure")]));
+		assert_eq!(config.value("target").unwrap(), None);
+	}
+
+	#[test]
+	fn profile_replaces_values() {
+		let config = Config::parse(CONFIG, Some("server")).unwrap();
+		assert_eq!(config.value("delay").unwrap(), Some(String::from("5000")));
+		assert_eq!(config.value("mode").unwrap(), Some(String::from("log")));
+		assert_eq!(config.value("target").unwrap(), Some(String::from("udp://127.0.0.1:8089")));
+		//tables are merged key by key, untouched keys stay
+		assert_eq!(config.number("thresholds.critical").unwrap(), Some(95.0));
+		assert_eq!(config.number("thresholds.warning").unwrap(), Some(60.0));
+		assert_eq!(config.flag("color").unwrap(), Some(false));
+	}
+
+	#[test]
+	fn errors() {
+		assert!(Config::parse(CONFIG, Some("laptop")).err().unwrap().contains("laptop"));
+		assert!(Config::parse("dealy = 100", None).err().unwrap().contains("dealy"));
+		assert!(Config::parse("delay = ", None).is_err());
+		let config = Config::parse("color = \"no\"\nsections = [1]\ndelay = true", None).unwrap();
+		assert!(config.flag("color").is_err());
+		assert!(config.list("sections").is_err());
+		assert!(config.value("delay").is_err());
+	}
+
+	#[test]
+	fn explicit_file_must_exist() {
+		assert!(Config::load(Some("/nonexistent/run-info.toml"), None).is_err());
+		let path = env::temp_dir().join(format!("run-info-config-{}.toml", ::std::process::id()));
+		::std::fs::write(&path, "delay = 250\n").unwrap();
+		let config = Config::load(path.to_str(), None).unwrap();
+		assert_eq!(config.value("delay").unwrap(), Some(String::from("250")));
+		::std::fs::remove_file(&path).unwrap();
+	}
+}
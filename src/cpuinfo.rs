@@ -3,10 +3,10 @@ Read /proc/stat to get the cpu usage
 Calculate difference between two datasets to get momentary load
 */
 
-use std::fs::File;
-use std::io::Read;
 use std::io::Result;
 
+use fsutils::read_file;
+
 pub struct CPULoad {
 	pub busy: u64,
 	pub idle: u64
@@ -15,6 +15,7 @@ pub struct CPULoad {
 pub struct CPUInfo {
 	pub cores: usize,
 	pub cores_load: Vec<CPULoad>,
+	pub core_ids: Vec<usize>, //the number of each core in cores_load ("cpu3" -> 3)
 	pub total_load: CPULoad,
 	pub processes: usize
 }
@@ -24,6 +25,7 @@ impl CPUInfo {
 		CPUInfo {
 			cores: 0,
 			cores_load: Vec::new(),
+			core_ids: Vec::new(),
 			total_load: CPULoad{busy: 0, idle: 0},
 			processes: 0
 		}
@@ -58,6 +60,7 @@ impl CPUInfo {
 					busy: busy,
 					idle: idle
 				});
+				self.core_ids.push(info_vec[0][3..].parse::<usize>().expect("incorrect cpu information format"));
 			}
 			else if line.starts_with("procs_running") {
 				//parsing the no. of processes (2nd entry in the "processes" line)
@@ -90,6 +93,7 @@ impl CPUInfo {
 	pub fn calculate_delta(delta: &mut CPUInfo, old: &CPUInfo, new: &CPUInfo) {
 		delta.cores = new.cores; //core number and processes stay the same
 		delta.processes = new.processes;
+		delta.core_ids = new.core_ids.clone();
 
 		delta.total_load.busy = new.total_load.busy - old.total_load.busy;
 		delta.total_load.idle = new.total_load.idle - old.total_load.idle;
@@ -104,10 +108,3 @@ impl CPUInfo {
 		}
 	}
 }
-
-fn read_file(location: &str) -> Result<String> {
-	let mut file = try!(File::open(location));
-	let mut ret_val = String::new();
-	try!(file.read_to_string(&mut ret_val));
-	Ok(ret_val)
-}
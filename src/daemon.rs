@@ -0,0 +1,118 @@
+/*
+Running in the background (--daemon): detaching from the terminal and keeping a PID file
+*/
+
+use libc;
+
+use process::send_signal;
+use fsutils::read_file;
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+
+//forks, the parent exits and the child continues in a new session without a terminal
+pub fn detach() -> Result<()> {
+	match unsafe { libc::fork() } {
+		-1 => return Err(Error::last_os_error()),
+		0 => {},
+		_ => unsafe { libc::_exit(0) }
+	}
+	if unsafe { libc::setsid() } == -1 {
+		return Err(Error::last_os_error());
+	}
+	//the daemon should not keep a mount point busy
+	try!(env::set_current_dir("/"));
+	let null = try!(OpenOptions::new().read(true).write(true).open("/dev/null"));
+	for fd in 0..3 {
+		if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+			return Err(Error::last_os_error());
+		}
+	}
+	Ok(())
+}
+
+//removed again when run-info exits
+pub struct PidFile {
+	path: PathBuf
+}
+
+impl PidFile {
+	//fails if the file names a process that is still running
+	pub fn create<P: AsRef<Path>>(path: P) -> Result<PidFile> {
+		let path = try!(env::current_dir()).join(path);
+		if let Ok(content) = read_file(&path) {
+			if let Ok(pid) = content.trim().parse::<i32>() {
+				let alive = match send_signal(pid, 0) {
+					Ok(_) => true,
+					Err(err) => err.raw_os_error() == Some(libc::EPERM)
+				};
+				if alive && pid != process::id() as i32 {
+					return Err(Error::new(ErrorKind::AlreadyExists, format!("already running with pid {}", pid)));
+				}
+			}
+		}
+		let pid_file = PidFile { path: path };
+		try!(pid_file.write());
+		Ok(pid_file)
+	}
+
+	//again after detach(), which changes the pid
+	pub fn write(&self) -> Result<()> {
+		let mut file = try!(File::create(&self.path));
+		writeln!(file, "{}", process::id())
+	}
+}
+
+impl Drop for PidFile {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::process::Command;
+
+	fn temp_dir(test: &str) -> PathBuf {
+		let dir = env::temp_dir().join(format!("run-info-daemon-{}-{}", test, process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	#[test]
+	fn pid_file_is_written_and_removed() {
+		let dir = temp_dir("pid");
+		let path = dir.join("run-info.pid");
+		{
+			let _pid_file = PidFile::create(&path).unwrap();
+			assert_eq!(read_file(&path).unwrap(), format!("{}\n", process::id()));
+		}
+		assert!(!path.exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn running_process_is_not_replaced() {
+		let dir = temp_dir("running");
+		let path = dir.join("run-info.pid");
+		let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+		fs::write(&path, format!("{}\n", child.id())).unwrap();
+		let err = PidFile::create(&path).err().expect("the running process was replaced");
+		assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+		//a stale file of an exited process is taken over
+		child.kill().unwrap();
+		child.wait().unwrap();
+		let pid_file = PidFile::create(&path).unwrap();
+		assert_eq!(read_file(&path).unwrap(), format!("{}\n", process::id()));
+		drop(pid_file);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,357 @@
+/*
+Machine-readable output for metric collectors (--format)
+The lines are written to stdout or sent to a local UDP/TCP listener (--target)
+*/
+
+use cpuinfo::CPUInfo;
+use meminfo::MemInfo;
+use printutils::calc_cpu_load_percentage;
+use fsutils::read_file;
+use logfile::LogFile;
+
+use std::io::{self, Error, ErrorKind, Result, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+
+pub enum Format {
+	Text, //the terminal UI or the -l line
+	Influx,
+	StatsD,
+	Graphite
+}
+
+impl Format {
+	pub fn from_str(name: &str) -> Option<Format> {
+		match name {
+			"text" => Some(Format::Text),
+			"influx" => Some(Format::Influx),
+			"statsd" => Some(Format::StatsD),
+			"graphite" => Some(Format::Graphite),
+			_ => None
+		}
+	}
+
+	//used if no --target is given
+	pub fn default_target(&self) -> Option<&'static str> {
+		match *self {
+			Format::StatsD => Some("udp://127.0.0.1:8125"),
+			Format::Graphite => Some("tcp://127.0.0.1:2003"),
+			_ => None
+		}
+	}
+}
+
+//datagrams are kept below a common MTU, collectors drop what does not fit into a single packet
+const MAX_DATAGRAM: usize = 1432;
+
+pub enum Sink {
+	Stdout,
+	Udp(UdpSocket, SocketAddr),
+	Tcp(SocketAddr, Option<TcpStream>), //reconnected on the next tick if the connection broke
+	File(LogFile) //--output
+}
+
+impl Sink {
+	//"udp://127.0.0.1:8089", "tcp://127.0.0.1:8094" or None for stdout
+	pub fn open(target: Option<&str>) -> Result<Sink> {
+		let target = match target {
+			Some(v) => v,
+			None => return Ok(Sink::Stdout)
+		};
+		let (scheme, address) = match target.find("://") {
+			Some(index) => (&target[..index], &target[index + 3..]),
+			None => return Err(Error::new(ErrorKind::InvalidInput, "expected udp://host:port or tcp://host:port"))
+		};
+		let address = match address.parse::<SocketAddr>() {
+			Ok(v) => v,
+			Err(_) => return Err(Error::new(ErrorKind::InvalidInput, "expected an ip address and a port"))
+		};
+		match scheme {
+			"udp" => {
+				let local = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+				let socket = try!(UdpSocket::bind(local));
+				Ok(Sink::Udp(socket, address))
+			},
+			"tcp" => {
+				let stream = try!(TcpStream::connect(address));
+				Ok(Sink::Tcp(address, Some(stream)))
+			},
+			_ => Err(Error::new(ErrorKind::InvalidInput, "expected udp://host:port or tcp://host:port"))
+		}
+	}
+
+	//lines are separated and terminated by '\n'
+	pub fn send(&mut self, lines: &str) -> Result<()> {
+		match *self {
+			Sink::Stdout => {
+				let stdout = io::stdout();
+				let mut stdout = stdout.lock();
+				try!(stdout.write_all(lines.as_bytes()));
+				stdout.flush()
+			},
+			Sink::Udp(ref socket, address) => {
+				for datagram in datagrams(lines, MAX_DATAGRAM) {
+					try!(socket.send_to(datagram.as_bytes(), address));
+				}
+				Ok(())
+			},
+			Sink::Tcp(address, ref mut stream) => {
+				if stream.is_none() {
+					*stream = Some(try!(TcpStream::connect(address)));
+				}
+				let result = stream.as_mut().unwrap().write_all(lines.as_bytes());
+				if result.is_err() {
+					*stream = None;
+				}
+				result
+			},
+			Sink::File(ref mut file) => file.write(lines)
+		}
+	}
+
+	//on SIGHUP
+	pub fn reopen(&mut self) -> Result<()> {
+		match *self {
+			Sink::Tcp(_, ref mut stream) => {
+				*stream = None;
+				Ok(())
+			},
+			Sink::File(ref mut file) => file.reopen(),
+			_ => Ok(())
+		}
+	}
+}
+
+//splits the lines into chunks of whole lines, a single longer line gets a chunk of its own
+fn datagrams(lines: &str, size: usize) -> Vec<&str> {
+	let mut result = Vec::new();
+	let mut start = 0;
+	let mut end = 0;
+	for line in lines.split_terminator('\n') {
+		let line_end = end + line.len() + 1;
+		if line_end - start > size && end > start {
+			result.push(&lines[start..end]);
+			start = end;
+		}
+		end = line_end.min(lines.len());
+	}
+	if end > start {
+		result.push(&lines[start..end]);
+	}
+	result
+}
+
+//"{host}" in the prefix is replaced by the hostname, its dots would start new levels of the metric path
+pub fn expand_prefix(prefix: &str) -> String {
+	let host = read_file("/proc/sys/kernel/hostname")
+		.map(|host| host.trim().replace('.', "_"))
+		.unwrap_or(String::from("unknown"));
+	prefix.replace("{host}", &host)
+}
+
+//(name, value) of each gauge, loads in percent, memory in bytes
+fn gauges(cpu: &CPUInfo, mem: &MemInfo) -> Vec<(String, String)> {
+	let mut result = Vec::new();
+	result.push((String::from("cpu.total"), format!("{:.2}", calc_cpu_load_percentage(&cpu.total_load) * 100.0)));
+	for (core, load) in cpu.core_ids.iter().zip(cpu.cores_load.iter()) {
+		result.push((format!("cpu.core{}", core), format!("{:.2}", calc_cpu_load_percentage(load) * 100.0)));
+	}
+	result.push((String::from("mem.used"), format!("{}", mem.used)));
+	result.push((String::from("mem.total"), format!("{}", mem.total)));
+	result.push((String::from("swap.used"), format!("{}", mem.swap_used)));
+	result.push((String::from("swap.total"), format!("{}", mem.swap_total)));
+	result
+}
+
+pub fn statsd_lines(cpu: &CPUInfo, mem: &MemInfo, prefix: &str) -> String {
+	let mut lines = String::new();
+	for (name, value) in gauges(cpu, mem) {
+		lines.push_str(&format!("{}.{}:{}|g\n", prefix, name, value));
+	}
+	lines
+}
+
+//Graphite plaintext protocol, timestamps in seconds
+pub fn graphite_lines(cpu: &CPUInfo, mem: &MemInfo, prefix: &str, timestamp: u64) -> String {
+	let mut lines = String::new();
+	for (name, value) in gauges(cpu, mem) {
+		lines.push_str(&format!("{}.{} {} {}\n", prefix, name, value, timestamp));
+	}
+	lines
+}
+
+//InfluxDB line protocol, loads in percent, memory in bytes
+//without a timestamp the server uses the time the line arrived
+pub fn influx_lines(cpu: &CPUInfo, mem: &MemInfo, timestamp: Option<u64>) -> String {
+	let timestamp = timestamp.map(|timestamp| format!(" {}", timestamp)).unwrap_or(String::new());
+	let mut lines = String::new();
+	let total = calc_cpu_load_percentage(&cpu.total_load) * 100.0;
+	lines.push_str(&format!("cpu,core=total busy={:.2},idle={:.2}{}\n", total, 100.0 - total, timestamp));
+	for (core, load) in cpu.core_ids.iter().zip(cpu.cores_load.iter()) {
+		let busy = calc_cpu_load_percentage(load) * 100.0;
+		lines.push_str(&format!("cpu,core={} busy={:.2},idle={:.2}{}\n", core, busy, 100.0 - busy, timestamp));
+	}
+	lines.push_str(&format!("mem used={}i,total={}i{}\n", mem.used, mem.total, timestamp));
+	lines.push_str(&format!("swap used={}i,total={}i{}\n", mem.swap_used, mem.swap_total, timestamp));
+	lines
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use cpuinfo::CPULoad;
+
+	use std::io::{BufRead, BufReader};
+	use std::net::TcpListener;
+	use std::time::Duration;
+
+	fn sample() -> (CPUInfo, MemInfo) {
+		let mut cpu = CPUInfo::new();
+		cpu.cores = 2;
+		cpu.core_ids = vec![0, 3];
+		cpu.cores_load = vec![CPULoad {busy: 25, idle: 75}, CPULoad {busy: 100, idle: 0}];
+		cpu.total_load = CPULoad {busy: 125, idle: 75};
+		let mut mem = MemInfo::new();
+		mem.total = 8000;
+		mem.used = 2000;
+		mem.swap_total = 1000;
+		mem.swap_used = 0;
+		(cpu, mem)
+	}
+
+	#[test]
+	fn influx_line_protocol() {
+		let (cpu, mem) = sample();
+		assert_eq!(influx_lines(&cpu, &mem, Some(1500000000000000000)),
+			"cpu,core=total busy=62.50,idle=37.50 1500000000000000000\n\
+			 cpu,core=0 busy=25.00,idle=75.00 1500000000000000000\n\
+			 cpu,core=3 busy=100.00,idle=0.00 1500000000000000000\n\
+			 mem used=2000i,total=8000i 1500000000000000000\n\
+			 swap used=0i,total=1000i 1500000000000000000\n");
+	}
+
+	#[test]
+	fn influx_without_timestamp() {
+		let (cpu, mem) = sample();
+		let lines = influx_lines(&cpu, &mem, None);
+		assert!(lines.starts_with("cpu,core=total busy=62.50,idle=37.50\n"));
+		assert!(lines.ends_with("swap used=0i,total=1000i\n"));
+	}
+
+	#[test]
+	fn statsd_gauges() {
+		let (cpu, mem) = sample();
+		assert_eq!(statsd_lines(&cpu, &mem, "run-info.host"),
+			"run-info.host.cpu.total:62.50|g\n\
+			 run-info.host.cpu.core0:25.00|g\n\
+			 run-info.host.cpu.core3:100.00|g\n\
+			 run-info.host.mem.used:2000|g\n\
+			 run-info.host.mem.total:8000|g\n\
+			 run-info.host.swap.used:0|g\n\
+			 run-info.host.swap.total:1000|g\n");
+	}
+
+	#[test]
+	fn graphite_plaintext() {
+		let (cpu, mem) = sample();
+		let lines = graphite_lines(&cpu, &mem, "servers.db1", 1500000000);
+		assert!(lines.starts_with("servers.db1.cpu.total 62.50 1500000000\nservers.db1.cpu.core0 25.00 1500000000\n"));
+		assert!(lines.ends_with("servers.db1.swap.total 1000 1500000000\n"));
+		assert_eq!(lines.lines().count(), 7);
+	}
+
+	#[test]
+	fn prefix_with_hostname() {
+		let host = read_file("/proc/sys/kernel/hostname").unwrap().trim().replace('.', "_");
+		assert_eq!(expand_prefix("run-info.{host}.sys"), format!("run-info.{}.sys", host));
+		assert_eq!(expand_prefix("plain"), "plain");
+	}
+
+	#[test]
+	fn datagrams_split_between_lines() {
+		assert_eq!(datagrams("aaaa\nbb\ncc\n", 8), vec!["aaaa\nbb\n", "cc\n"]);
+		assert_eq!(datagrams("aaaaaaaaaa\nb\n", 8), vec!["aaaaaaaaaa\n", "b\n"]);
+		assert_eq!(datagrams("a\nb", 100), vec!["a\nb"]);
+		assert!(datagrams("", 8).is_empty());
+	}
+
+	#[test]
+	fn statsd_gauges_over_udp() {
+		let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+		listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		let target = format!("udp://{}", listener.local_addr().unwrap());
+		let mut sink = Sink::open(Some(&target)).unwrap();
+		//enough cores for more than one datagram
+		let (mut cpu, mem) = sample();
+		cpu.core_ids = (0..128).collect();
+		cpu.cores_load = (0..128).map(|core| CPULoad {busy: core, idle: 128 - core}).collect();
+		let prefix = expand_prefix("run-info.{host}");
+		let lines = statsd_lines(&cpu, &mem, &prefix);
+		assert!(lines.len() > MAX_DATAGRAM);
+		sink.send(&lines).unwrap();
+
+		let mut received = String::new();
+		let mut buffer = [0; 2 * MAX_DATAGRAM];
+		while received.len() < lines.len() {
+			let length = listener.recv(&mut buffer).unwrap();
+			//each datagram fits into the MTU and only holds whole gauges
+			assert!(length <= MAX_DATAGRAM, "{}", length);
+			let datagram = String::from_utf8(buffer[..length].to_vec()).unwrap();
+			assert!(datagram.ends_with('\n'));
+			for line in datagram.lines() {
+				assert!(line.starts_with(&format!("{}.", prefix)), "{}", line);
+				assert!(!line.contains("{host}"));
+				//name:value|g
+				assert!(line.ends_with("|g"), "{}", line);
+				let (name, value) = line[..line.len() - 2].split_at(line.find(':').expect("no value"));
+				assert!(!name.contains(' '), "{}", line);
+				value[1..].parse::<f64>().unwrap();
+			}
+			received.push_str(&datagram);
+		}
+		assert_eq!(received, lines);
+		assert!(received.contains(&format!("{}.cpu.core127:99.22|g\n", prefix)));
+	}
+
+	#[test]
+	fn invalid_targets() {
+		for target in &["127.0.0.1:8089", "http://127.0.0.1:8089", "udp://localhost", "udp://127.0.0.1"] {
+			assert_eq!(Sink::open(Some(target)).err().map(|err| err.kind()), Some(ErrorKind::InvalidInput), "{}", target);
+		}
+	}
+
+	#[test]
+	fn send_over_udp() {
+		let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+		listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		let target = format!("udp://{}", listener.local_addr().unwrap());
+		let mut sink = Sink::open(Some(&target)).unwrap();
+		sink.send("mem used=1i,total=2i 3\n").unwrap();
+		let mut buffer = [0; 64];
+		let length = listener.recv(&mut buffer).unwrap();
+		assert_eq!(&buffer[..length], b"mem used=1i,total=2i 3\n");
+	}
+
+	#[test]
+	fn send_over_tcp_and_reconnect() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let target = format!("tcp://{}", listener.local_addr().unwrap());
+		let mut sink = Sink::open(Some(&target)).unwrap();
+		sink.send("first 1\n").unwrap();
+		let (first, _) = listener.accept().unwrap();
+		let mut line = String::new();
+		BufReader::new(&first).read_line(&mut line).unwrap();
+		assert_eq!(line, "first 1\n");
+
+		//the collector restarts: the broken connection is dropped and opened again
+		drop(first);
+		if let Sink::Tcp(_, ref mut stream) = sink {
+			*stream = None;
+		}
+		sink.send("second 2\n").unwrap();
+		let (second, _) = listener.accept().unwrap();
+		line.clear();
+		BufReader::new(&second).read_line(&mut line).unwrap();
+		assert_eq!(line, "second 2\n");
+	}
+}
@@ -0,0 +1,76 @@
+/*
+Helpers for reading the files in /proc and /sys
+*/
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::io::Result;
+use std::path::Path;
+
+pub fn read_file<P: AsRef<Path>>(location: P) -> Result<String> {
+	let mut file = try!(File::open(location));
+	let mut ret_val = String::new();
+	try!(file.read_to_string(&mut ret_val));
+	Ok(ret_val)
+}
+
+//parses files made of "key value" lines (like cpu.stat or /proc/vmstat), lines without a numeric value are skipped
+pub fn read_keyed<P: AsRef<Path>>(location: P) -> Result<HashMap<String, u64>> {
+	let plain = try!(read_file(location));
+	let mut map = HashMap::new();
+	for line in plain.lines() {
+		let mut columns = line.split_whitespace();
+		if let (Some(key), Some(value)) = (columns.next(), columns.next()) {
+			if let Ok(value) = value.parse::<u64>() {
+				map.insert(key.to_owned(), value);
+			}
+		}
+	}
+	Ok(map)
+}
+
+//difference of a counter between two read_keyed() results, missing counters count as 0
+pub fn counter_delta(new: &HashMap<String, u64>, old: &HashMap<String, u64>, key: &str) -> u64 {
+	let new_value = new.get(key).cloned().unwrap_or(0);
+	let old_value = old.get(key).cloned().unwrap_or(0);
+	new_value.saturating_sub(old_value)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::env;
+	use std::fs;
+	use std::io::Write;
+
+	#[test]
+	fn keyed_files() {
+		let location = env::temp_dir().join(format!("run-info-keyed-{}", ::std::process::id()));
+		File::create(&location).unwrap().write_all(b"nr_free_pages 1234\npgfault 18446744073709551615\n\
+			some_text abc\nlonely\n\nnr_zspages 0\n").unwrap();
+		let map = read_keyed(&location).unwrap();
+		let _ = fs::remove_file(&location);
+		assert_eq!(map.len(), 3);
+		assert_eq!(map.get("nr_free_pages"), Some(&1234));
+		assert_eq!(map.get("pgfault"), Some(&u64::max_value()));
+		assert_eq!(map.get("nr_zspages"), Some(&0));
+		assert!(read_keyed(&location).is_err());
+	}
+
+	#[test]
+	fn counter_deltas() {
+		let old: HashMap<String, u64> = [("a", 10), ("b", 50), ("wraps", u64::max_value() - 5)].iter()
+			.map(|&(key, value)| (key.to_owned(), value)).collect();
+		let new: HashMap<String, u64> = [("a", 25), ("b", 7), ("c", 3), ("wraps", 4)].iter()
+			.map(|&(key, value)| (key.to_owned(), value)).collect();
+		assert_eq!(counter_delta(&new, &old, "a"), 15);
+		//a counter that went back (reset or wrapped) counts as no events, not as a huge rate
+		assert_eq!(counter_delta(&new, &old, "b"), 0);
+		assert_eq!(counter_delta(&new, &old, "wraps"), 0);
+		//a counter that appeared counts from 0, a missing one as 0
+		assert_eq!(counter_delta(&new, &old, "c"), 3);
+		assert_eq!(counter_delta(&new, &old, "d"), 0);
+	}
+}
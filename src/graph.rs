@@ -1,34 +1,273 @@
 /*
-The data structure for the CPU load history graph
+The data structure for the load history graphs
+A fixed capacity ring buffer with windowed statistics over the newest samples
 */
 
-const GRAPH_SIZE: usize = 51;
-type GraphType = f64;
+//number of values shown by the graph in normal mode
+pub const GRAPH_WIDTH: usize = 51;
 
-pub struct Graph {
-    data: [GraphType; GRAPH_SIZE]
+pub struct Graph<T> {
+    data: Vec<T>,
+    capacity: usize,
+    start: usize, //index of the oldest entry
+    len: usize
 }
 
-impl Graph {
-    pub fn new() -> Graph {
+impl<T: Copy> Graph<T> {
+    pub fn new(capacity: usize) -> Graph<T> {
+        assert!(capacity > 0);
         Graph {
-            data: [0.0; GRAPH_SIZE]
+            data: Vec::with_capacity(capacity),
+            capacity: capacity,
+            start: 0,
+            len: 0
         }
     }
 
-    //works like a queue
-    pub fn push(&mut self, entry: GraphType) {
-        for i in 0..GRAPH_SIZE - 1 {
-            self.data[i] = self.data[i+1];
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    //works like a queue, the oldest entry is dropped once the graph is full
+    pub fn push(&mut self, entry: T) {
+        if self.data.len() < self.capacity {
+            self.data.push(entry);
+            self.len += 1;
+        }
+        else {
+            self.data[self.start] = entry;
+            self.start = (self.start + 1) % self.data.len();
+        }
+    }
+
+    pub fn latest(&self) -> Option<T> {
+        self.get(self.len.wrapping_sub(1))
+    }
+
+    //0 is the oldest entry
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index >= self.len {
+            return None;
         }
-        self.data[GRAPH_SIZE - 1] = entry;
+        Some(self.data[(self.start + index) % self.data.len()])
+    }
+
+    //all entries, from oldest to newest
+    #[allow(dead_code)] //the windowed queries are general purpose, not every one is used by the printers
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        self.window(self.len)
     }
 
-    pub fn height_values(&self, max: usize) -> [usize; GRAPH_SIZE] {
-        let mut result = [0; GRAPH_SIZE];
-        for i in 0..GRAPH_SIZE {
-            result[i] = (self.data[i] * max as GraphType) as usize;
+    //the newest `size` entries (or less, if the graph does not hold that many), from oldest to newest
+    pub fn window<'a>(&'a self, size: usize) -> Iter<'a, T> {
+        let size = size.min(self.len);
+        Iter {
+            graph: self,
+            front: self.len - size,
+            back: self.len
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: Copy + Into<f64>> Graph<T> {
+    pub fn min(&self, window: usize) -> Option<f64> {
+        self.window(window).map(|v| v.into()).fold(None, |min, v| match min {
+            Some(m) if m <= v => Some(m),
+            _ => Some(v)
+        })
+    }
+
+    pub fn max(&self, window: usize) -> Option<f64> {
+        self.window(window).map(|v| v.into()).fold(None, |max, v| match max {
+            Some(m) if m >= v => Some(m),
+            _ => Some(v)
+        })
+    }
+
+    pub fn mean(&self, window: usize) -> Option<f64> {
+        let iter = self.window(window);
+        let count = iter.len();
+        if count == 0 {
+            return None;
+        }
+        Some(iter.map(|v| v.into()).sum::<f64>() / count as f64)
+    }
+
+    //nearest-rank percentile, p ranges from 0.0 to 1.0
+    pub fn percentile(&self, window: usize, p: f64) -> Option<f64> {
+        let mut sorted: Vec<f64> = self.window(window).map(|v| v.into()).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(|a, b| a.partial_cmp(b).expect("invalid graph value"));
+        let rank = (p * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1).min(sorted.len() - 1)])
+    }
+
+    //exponentially weighted moving average over all entries, alpha is the weight of the newest entry
+    pub fn ewma(&self, alpha: f64) -> Option<f64> {
+        self.iter().map(|v| v.into()).fold(None, |avg, v| match avg {
+            Some(a) => Some(alpha * v + (1.0 - alpha) * a),
+            None => Some(v)
+        })
+    }
+
+    //squeezes the whole capacity into `width` columns, each being the mean of its entries
+    //columns that are not filled yet are 0, if the capacity is smaller than the width the newest entries are right-aligned
+    pub fn downsample(&self, width: usize) -> Vec<f64> {
+        let capacity = self.capacity.max(width);
+        let empty = capacity - self.len; //slots in front of the oldest entry
+        let mut result = Vec::with_capacity(width);
+        for column in 0..width {
+            let from = column * capacity / width;
+            let to = ((column + 1) * capacity / width).max(from + 1);
+            let mut sum = 0.0;
+            let mut count = 0;
+            for slot in from.max(empty)..to {
+                sum += self.get(slot - empty).expect("slot out of range").into();
+                count += 1;
+            }
+            result.push(if count > 0 { sum / count as f64 } else { 0.0 });
         }
         result
     }
 }
+
+pub struct Iter<'a, T: 'a> {
+    graph: &'a Graph<T>,
+    front: usize,
+    back: usize
+}
+
+impl<'a, T: Copy> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.front >= self.back {
+            return None;
+        }
+        self.front += 1;
+        self.graph.get(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: Copy> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(capacity: usize, values: &[f64]) -> Graph<f64> {
+        let mut graph = Graph::new(capacity);
+        for value in values {
+            graph.push(*value);
+        }
+        graph
+    }
+
+    #[test]
+    fn push_wraps_around() {
+        let graph = filled(3, &[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(graph.get(0), Some(3.0));
+        assert_eq!(graph.get(2), Some(5.0));
+        assert_eq!(graph.get(3), None);
+        assert_eq!(graph.latest(), Some(5.0));
+        assert_eq!(graph.window(3).collect::<Vec<f64>>(), vec![3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn empty_graph() {
+        let graph: Graph<f64> = Graph::new(4);
+        assert!(graph.is_empty());
+        assert_eq!(graph.latest(), None);
+        assert_eq!(graph.mean(10), None);
+        assert_eq!(graph.window(2).len(), 0);
+    }
+
+    #[test]
+    fn window_is_bounded_by_the_entries() {
+        let graph = filled(5, &[1.0, 2.0, 3.0]);
+        assert_eq!(graph.window(2).collect::<Vec<f64>>(), vec![2.0, 3.0]);
+        assert_eq!(graph.window(10).len(), 3);
+        assert_eq!(graph.window(0).next(), None);
+        assert_eq!(graph.mean(2), Some(2.5));
+        assert_eq!(graph.mean(10), Some(2.0));
+    }
+
+    #[test]
+    fn iter_follows_the_ring() {
+        let graph = filled(3, &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(graph.iter().collect::<Vec<f64>>(), vec![2.0, 3.0, 4.0]);
+        assert_eq!(graph.iter().len(), 3);
+        let graph: Graph<f64> = Graph::new(3);
+        assert_eq!(graph.iter().next(), None);
+    }
+
+    #[test]
+    fn min_and_max() {
+        //the ring has wrapped: 6.0, 2.0, 8.0, 4.0 are left
+        let graph = filled(4, &[9.0, 1.0, 6.0, 2.0, 8.0, 4.0]);
+        assert_eq!(graph.min(2), Some(4.0));
+        assert_eq!(graph.max(2), Some(8.0));
+        assert_eq!(graph.min(10), Some(2.0));
+        assert_eq!(graph.max(10), Some(8.0));
+        assert_eq!(graph.min(0), None);
+        let graph: Graph<f64> = Graph::new(4);
+        assert_eq!(graph.min(4), None);
+        assert_eq!(graph.max(4), None);
+    }
+
+    #[test]
+    fn percentiles() {
+        let graph = filled(10, &[5.0, 1.0, 4.0, 2.0, 3.0]);
+        assert_eq!(graph.percentile(10, 0.0), Some(1.0));
+        assert_eq!(graph.percentile(10, 0.5), Some(3.0));
+        assert_eq!(graph.percentile(10, 0.8), Some(4.0));
+        assert_eq!(graph.percentile(10, 1.0), Some(5.0));
+        //only the newest two: 2.0 and 3.0
+        assert_eq!(graph.percentile(2, 0.5), Some(2.0));
+        let graph = filled(3, &[10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(graph.percentile(3, 1.0), Some(40.0));
+        assert_eq!(graph.percentile(3, 0.1), Some(20.0));
+        let graph: Graph<f64> = Graph::new(3);
+        assert_eq!(graph.percentile(3, 0.5), None);
+    }
+
+    #[test]
+    fn ewma_weights_the_newest_entries() {
+        let graph = filled(4, &[0.0, 1.0]);
+        assert_eq!(graph.ewma(0.5), Some(0.5));
+        //after wrapping only 2.0, 2.0, 4.0 are left
+        let graph = filled(3, &[100.0, 2.0, 2.0, 4.0]);
+        assert_eq!(graph.ewma(0.5), Some(3.0));
+        assert_eq!(graph.ewma(1.0), Some(4.0));
+        let graph: Graph<f64> = Graph::new(3);
+        assert_eq!(graph.ewma(0.5), None);
+    }
+
+    #[test]
+    fn downsample_squeezes_the_capacity() {
+        let graph = filled(6, &[1.0, 3.0, 2.0, 4.0, 5.0, 7.0]);
+        assert_eq!(graph.downsample(3), vec![2.0, 3.0, 6.0]);
+    }
+
+    #[test]
+    fn downsample_right_aligns_the_newest_entries() {
+        //a graph that is not full yet leaves the oldest columns empty
+        let graph = filled(6, &[2.0, 4.0]);
+        assert_eq!(graph.downsample(3), vec![0.0, 0.0, 3.0]);
+        //a capacity smaller than the width puts the entries into the last columns
+        let graph = filled(2, &[1.0, 2.0, 3.0]);
+        assert_eq!(graph.downsample(4), vec![0.0, 0.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,247 @@
+/*
+Load histories of the total CPU, each core, RAM and swap
+Each history holds averages over one graph column of the last minute, 10 second averages of the last hour
+and 1 minute averages of the last day
+The samples are put into the averages by the time they were taken, so the tiers span the same time at any delay
+*/
+
+use cpuinfo::CPUInfo;
+use meminfo::MemInfo;
+use pressure::PressureInfo;
+use graph::{Graph, GRAPH_WIDTH};
+use printutils::calc_cpu_load_percentage;
+
+use time;
+
+const NS_PER_SECOND: u64 = 1000000000;
+const TEN_SECONDS_PER_HOUR: usize = 360;
+const MINUTES_PER_DAY: usize = 1440;
+
+//the time span the graph is showing
+#[derive(Clone, Copy, PartialEq)]
+pub enum Zoom {
+	Minute,
+	Hour,
+	Day
+}
+
+impl Zoom {
+	pub fn from_str(name: &str) -> Option<Zoom> {
+		match name {
+			"minute" => Some(Zoom::Minute),
+			"hour" => Some(Zoom::Hour),
+			"day" => Some(Zoom::Day),
+			_ => None
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		match *self {
+			Zoom::Minute => "last minute",
+			Zoom::Hour => "last hour",
+			Zoom::Day => "last 24 hours"
+		}
+	}
+
+	pub fn zoom_in(&self) -> Zoom {
+		match *self {
+			Zoom::Day => Zoom::Hour,
+			_ => Zoom::Minute
+		}
+	}
+
+	pub fn zoom_out(&self) -> Zoom {
+		match *self {
+			Zoom::Minute => Zoom::Hour,
+			_ => Zoom::Day
+		}
+	}
+}
+
+//averages over fixed time slots, a slot is finished by the first sample taken after it
+pub struct Tier {
+	pub graph: Graph<f64>,
+	slot_ns: u64,
+	slot: u64, //number of the slot that is being filled (time / slot_ns)
+	sum: f64,
+	count: usize
+}
+
+impl Tier {
+	//the tier spans capacity * slot_ns
+	fn new(capacity: usize, slot_ns: u64) -> Tier {
+		Tier {
+			graph: Graph::new(capacity),
+			slot_ns: slot_ns,
+			slot: 0,
+			sum: 0.0,
+			count: 0
+		}
+	}
+
+	fn push(&mut self, now_ns: u64, value: f64) {
+		let slot = now_ns / self.slot_ns;
+		if slot != self.slot && self.count > 0 {
+			let avg = self.sum / self.count as f64;
+			//slots without a sample (delay longer than a slot, suspended machine) repeat the last average
+			let skipped = (slot.saturating_sub(self.slot + 1) as usize).min(self.graph.capacity());
+			for _ in 0..skipped + 1 {
+				self.graph.push(avg);
+			}
+			self.sum = 0.0;
+			self.count = 0;
+		}
+		self.slot = slot;
+		self.sum += value;
+		self.count += 1;
+	}
+}
+
+pub struct TieredGraph {
+	pub minute: Tier,
+	pub hour: Tier,
+	pub day: Tier
+}
+
+impl TieredGraph {
+	pub fn new() -> TieredGraph {
+		TieredGraph {
+			//one slot per graph column
+			minute: Tier::new(GRAPH_WIDTH, 60 * NS_PER_SECOND / GRAPH_WIDTH as u64),
+			hour: Tier::new(TEN_SECONDS_PER_HOUR, 10 * NS_PER_SECOND),
+			day: Tier::new(MINUTES_PER_DAY, 60 * NS_PER_SECOND)
+		}
+	}
+
+	pub fn push(&mut self, value: f64) {
+		self.push_at(time::precise_time_ns(), value);
+	}
+
+	pub fn push_at(&mut self, now_ns: u64, value: f64) {
+		self.minute.push(now_ns, value);
+		self.hour.push(now_ns, value);
+		self.day.push(now_ns, value);
+	}
+
+	//mean of the last n minutes, taken from the finest tier that covers them
+	pub fn mean(&self, minutes: usize) -> f64 {
+		if minutes <= 1 || self.hour.graph.is_empty() {
+			return self.minute.graph.mean(GRAPH_WIDTH * minutes).unwrap_or(0.0);
+		}
+		if minutes <= 60 || self.day.graph.is_empty() {
+			return self.hour.graph.mean(minutes * 6).unwrap_or(0.0);
+		}
+		self.day.graph.mean(minutes).unwrap_or(0.0)
+	}
+
+	//the values of the tier that belongs to the zoom level, squeezed into `width` columns
+	pub fn columns(&self, zoom: Zoom, width: usize) -> Vec<f64> {
+		match zoom {
+			Zoom::Minute => self.minute.graph.downsample(width),
+			Zoom::Hour => self.hour.graph.downsample(width),
+			Zoom::Day => self.day.graph.downsample(width)
+		}
+	}
+}
+
+pub struct History {
+	pub total: TieredGraph,
+	pub cores: Vec<TieredGraph>,
+	pub ram: TieredGraph,
+	pub swap: TieredGraph,
+	//"some" stall share of the last 10 seconds
+	pub pressure_cpu: TieredGraph,
+	pub pressure_memory: TieredGraph,
+	pub pressure_io: TieredGraph
+}
+
+impl History {
+	pub fn new() -> History {
+		History {
+			total: TieredGraph::new(),
+			cores: Vec::new(),
+			ram: TieredGraph::new(),
+			swap: TieredGraph::new(),
+			pressure_cpu: TieredGraph::new(),
+			pressure_memory: TieredGraph::new(),
+			pressure_io: TieredGraph::new()
+		}
+	}
+
+	//takes the cpu delta of the current tick
+	pub fn record(&mut self, cpu: &CPUInfo, mem: &MemInfo) {
+		//the very first delta spans no time at all
+		if cpu.total_load.busy + cpu.total_load.idle == 0 {
+			return;
+		}
+		self.total.push(calc_cpu_load_percentage(&cpu.total_load));
+		while self.cores.len() < cpu.cores_load.len() {
+			self.cores.push(TieredGraph::new());
+		}
+		for (core, core_load) in cpu.cores_load.iter().enumerate() {
+			self.cores[core].push(calc_cpu_load_percentage(core_load));
+		}
+		self.ram.push(mem.memory_use());
+		self.swap.push(mem.swap_use());
+	}
+
+	pub fn record_pressure(&mut self, pressure: &PressureInfo) {
+		self.pressure_cpu.push(pressure.cpu.some.avg10);
+		self.pressure_memory.push(pressure.memory.some.avg10);
+		self.pressure_io.push(pressure.io.some.avg10);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	//samples every delay_ms for the given time, starting at a slot boundary
+	fn sampled(delay_ms: u64, secs: u64, value: f64) -> TieredGraph {
+		let mut graph = TieredGraph::new();
+		let mut now = 0;
+		while now <= secs * NS_PER_SECOND {
+			graph.push_at(now, value);
+			now += delay_ms * 1000000;
+		}
+		graph
+	}
+
+	#[test]
+	fn tiers_are_bucketed_by_time() {
+		for delay in &[500, 1500, 5000] {
+			let graph = sampled(*delay, 120, 0.5);
+			//every 10 seconds of the two minutes make up one slot, the last one is still being filled
+			assert_eq!(graph.hour.graph.window(TEN_SECONDS_PER_HOUR).len(), 12, "delay {}", delay);
+			assert_eq!(graph.day.graph.window(MINUTES_PER_DAY).len(), 2, "delay {}", delay);
+			//the minute tier is full after two minutes, no matter how often samples are taken
+			assert_eq!(graph.minute.graph.window(GRAPH_WIDTH + 1).len(), GRAPH_WIDTH, "delay {}", delay);
+		}
+	}
+
+	#[test]
+	fn slots_hold_averages() {
+		let mut graph = TieredGraph::new();
+		graph.push_at(0, 0.2);
+		graph.push_at(5 * NS_PER_SECOND, 0.4);
+		graph.push_at(10 * NS_PER_SECOND, 1.0);
+		assert!((graph.hour.graph.latest().unwrap() - 0.3).abs() < 1e-9);
+		assert!((graph.mean(5) - 0.3).abs() < 1e-9);
+	}
+
+	#[test]
+	fn skipped_slots_repeat_the_last_average() {
+		let mut graph = TieredGraph::new();
+		graph.push_at(0, 0.5);
+		graph.push_at(35 * NS_PER_SECOND, 1.0);
+		//the slots 0 to 2 are finished, slot 3 is being filled
+		assert_eq!(graph.hour.graph.window(10).collect::<Vec<f64>>(), vec![0.5, 0.5, 0.5]);
+	}
+
+	#[test]
+	fn zoom_levels() {
+		assert_eq!(Zoom::Minute.zoom_out().label(), "last hour");
+		assert_eq!(Zoom::Day.zoom_in().zoom_in().label(), "last minute");
+		assert!(Zoom::from_str("week").is_none());
+	}
+}
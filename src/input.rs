@@ -0,0 +1,212 @@
+/*
+Reads key presses from the terminal without waiting for enter
+The terminal is switched to non-canonical mode without echo, Ctrl-C still raises SIGINT
+Clicks and the mouse wheel arrive as SGR mouse reports once the screen has enabled them
+*/
+
+use libc;
+
+use std::io;
+use std::mem;
+use std::str;
+
+use signals;
+use time;
+
+const STDIN: libc::c_int = 0;
+
+//the poll timeout is split into slices of this length to react quickly to a signal
+const POLL_SLICE: u64 = 50;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+	Up,
+	Down,
+	PageUp,
+	PageDown,
+	Home,
+	End,
+	Enter,
+	Escape,
+	Backspace,
+	Char(char),
+	Click(usize, usize), //line and column on the screen, starting at 0
+	ScrollUp,
+	ScrollDown
+}
+
+pub struct Input {
+	original: Option<libc::termios> //None if stdin is not a terminal
+}
+
+impl Input {
+	pub fn new() -> Input {
+		unsafe {
+			if libc::isatty(STDIN) == 0 {
+				return Input {original: None};
+			}
+			let mut original: libc::termios = mem::zeroed();
+			if libc::tcgetattr(STDIN, &mut original) != 0 {
+				return Input {original: None};
+			}
+			let mut raw = original;
+			raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+			raw.c_cc[libc::VMIN] = 0;
+			raw.c_cc[libc::VTIME] = 0;
+			libc::tcsetattr(STDIN, libc::TCSANOW, &raw);
+			Input {original: Some(original)}
+		}
+	}
+
+	//waits up to timeout ms, returns as soon as keys were pressed or the program should terminate
+	pub fn wait(&mut self, timeout: u64) -> Vec<Key> {
+		if self.original.is_none() {
+			signals::sleep(timeout);
+			return Vec::new();
+		}
+		let deadline = time::precise_time_ns() + timeout * 1000000;
+		loop {
+			let now = time::precise_time_ns();
+			if now >= deadline || signals::terminate_requested() {
+				return Vec::new();
+			}
+			let slice = ((deadline - now) / 1000000).min(POLL_SLICE).max(1);
+			let mut poll_fd = libc::pollfd {fd: STDIN, events: libc::POLLIN, revents: 0};
+			let ready = unsafe { libc::poll(&mut poll_fd, 1, slice as libc::c_int) };
+			if ready > 0 {
+				//the terminal is gone (closed window, dropped SSH session), polling again would return at once
+				if poll_fd.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0 {
+					signals::request_terminate();
+					return Vec::new();
+				}
+				match read_keys() {
+					Some(ref keys) if keys.is_empty() => {},
+					Some(keys) => return keys,
+					None => {
+						signals::request_terminate();
+						return Vec::new();
+					}
+				}
+			}
+		}
+	}
+
+	pub fn restore(&mut self) {
+		if let Some(original) = self.original.take() {
+			unsafe {
+				libc::tcsetattr(STDIN, libc::TCSANOW, &original);
+			}
+		}
+	}
+}
+
+//the mouse is only enabled if its reports can be read
+pub fn is_terminal() -> bool {
+	unsafe { libc::isatty(STDIN) != 0 }
+}
+
+impl Drop for Input {
+	fn drop(&mut self) {
+		self.restore();
+	}
+}
+
+//None at the end of the input, which poll reports as readable
+fn read_keys() -> Option<Vec<Key>> {
+	let mut buffer = [0u8; 64];
+	let count = unsafe { libc::read(STDIN, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+	if count == 0 {
+		return None;
+	}
+	if count < 0 {
+		let error = io::Error::last_os_error().raw_os_error();
+		return if error == Some(libc::EINTR) || error == Some(libc::EAGAIN) { Some(Vec::new()) } else { None };
+	}
+	Some(parse_keys(&buffer[..count as usize]))
+}
+
+//turns the bytes read from the terminal into keys, unknown escape sequences are dropped
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+	let mut keys = Vec::new();
+	let mut index = 0;
+	while index < bytes.len() {
+		match bytes[index] {
+			0x1b if index + 2 < bytes.len() && bytes[index + 1] == b'[' => {
+				let (key, length) = parse_escape(&bytes[index + 2..]);
+				if let Some(key) = key {
+					keys.push(key);
+				}
+				index += 2 + length;
+				continue;
+			},
+			0x1b => keys.push(Key::Escape),
+			b'\r' | b'\n' => keys.push(Key::Enter),
+			0x7f | 0x08 => keys.push(Key::Backspace),
+			byte if byte >= 0x20 && byte < 0x7f => keys.push(Key::Char(byte as char)),
+			_ => {}
+		}
+		index += 1;
+	}
+	keys
+}
+
+//parses what follows "ESC [", returns the key and the number of bytes used
+fn parse_escape(bytes: &[u8]) -> (Option<Key>, usize) {
+	//the sequence ends with the first byte in 0x40..0x7e
+	let end = match bytes.iter().position(|byte| *byte >= 0x40 && *byte <= 0x7e) {
+		Some(v) => v,
+		None => return (None, bytes.len())
+	};
+	if bytes[0] == b'<' {
+		return (parse_mouse(&bytes[1..end], bytes[end]), end + 1);
+	}
+	let key = match (&bytes[..end], bytes[end]) {
+		(b"", b'A') => Some(Key::Up),
+		(b"", b'B') => Some(Key::Down),
+		(b"", b'H') | (b"1", b'~') => Some(Key::Home),
+		(b"", b'F') | (b"4", b'~') => Some(Key::End),
+		(b"5", b'~') => Some(Key::PageUp),
+		(b"6", b'~') => Some(Key::PageDown),
+		_ => None
+	};
+	(key, end + 1)
+}
+
+//a SGR mouse report "ESC [ < button ; column ; line M", ending with 'm' when the button is released
+fn parse_mouse(parameters: &[u8], last: u8) -> Option<Key> {
+	let parameters = match str::from_utf8(parameters) {
+		Ok(v) => v,
+		Err(_) => return None
+	};
+	let numbers: Vec<usize> = parameters.split(';').filter_map(|number| number.parse().ok()).collect();
+	if numbers.len() != 3 || numbers[1] == 0 || numbers[2] == 0 || last != b'M' {
+		return None;
+	}
+	//shift, alt and control add 4, 8 and 16 to the button
+	match numbers[0] & !0b11100 {
+		0 => Some(Key::Click(numbers[2] - 1, numbers[1] - 1)),
+		64 => Some(Key::ScrollUp),
+		65 => Some(Key::ScrollDown),
+		_ => None //the other buttons and moves with a pressed button
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keys_and_escape_sequences() {
+		assert_eq!(parse_keys(b"q\x1b[A\x1b[6~\x1b[Z\r"), vec![Key::Char('q'), Key::Up, Key::PageDown, Key::Enter]);
+		assert_eq!(parse_keys(b"\x1b"), vec![Key::Escape]);
+	}
+
+	#[test]
+	fn mouse_reports() {
+		assert_eq!(parse_keys(b"\x1b[<0;12;5M\x1b[<0;12;5m"), vec![Key::Click(4, 11)]);
+		assert_eq!(parse_keys(b"\x1b[<64;1;1M\x1b[<65;1;1Mk"), vec![Key::ScrollUp, Key::ScrollDown, Key::Char('k')]);
+		//with control held, a right click and a drag
+		assert_eq!(parse_keys(b"\x1b[<16;3;3M\x1b[<2;3;3M\x1b[<32;4;3M"), vec![Key::Click(2, 2)]);
+		assert_eq!(parse_keys(b"\x1b[<0;0;1M\x1b[<0;99999999999999999999999;1M"), vec![]);
+	}
+}
@@ -0,0 +1,236 @@
+/*
+An append-only log file for the daemon mode (--output)
+It is rotated by size or by the hour/day into file.1, file.2 ... (gzipped if wanted), the oldest ones are removed
+*/
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Result, Write};
+use std::path::{Path, PathBuf};
+
+use time;
+
+#[derive(Clone, Copy)]
+pub enum Rotation {
+	Never,
+	Size(u64), //in bytes
+	Hourly,
+	Daily
+}
+
+impl Rotation {
+	//"10M", "500K", "1G", plain bytes, "hourly" or "daily"
+	pub fn from_str(value: &str) -> Option<Rotation> {
+		match value {
+			"never" => return Some(Rotation::Never),
+			"hourly" => return Some(Rotation::Hourly),
+			"daily" => return Some(Rotation::Daily),
+			_ => {}
+		}
+		let (number, unit) = match value.chars().last() {
+			Some('K') | Some('k') => (&value[..value.len() - 1], 1024),
+			Some('M') | Some('m') => (&value[..value.len() - 1], 1024 * 1024),
+			Some('G') | Some('g') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+			_ => (value, 1)
+		};
+		match number.parse::<u64>() {
+			Ok(v) if v > 0 => Some(Rotation::Size(v * unit)),
+			_ => None
+		}
+	}
+
+	//number of the hour/day (UTC) the given time falls into, it changes when the file is due
+	fn period(&self, secs: i64) -> i64 {
+		match *self {
+			Rotation::Hourly => secs / 3600,
+			Rotation::Daily => secs / 86400,
+			_ => 0
+		}
+	}
+}
+
+pub struct LogFile {
+	path: PathBuf, //absolute, the daemon changes its directory
+	file: File,
+	size: u64,
+	period: i64, //hour/day of the first line, or of the last change of a file that was already there
+	rotation: Rotation,
+	keep: usize, //rotated files
+	gzip: bool
+}
+
+impl LogFile {
+	pub fn open<P: AsRef<Path>>(path: P, rotation: Rotation, keep: usize, gzip: bool) -> Result<LogFile> {
+		let path = try!(env::current_dir()).join(path);
+		let (file, size, period) = try!(open_append(&path, &rotation));
+		Ok(LogFile {
+			path: path,
+			file: file,
+			size: size,
+			period: period,
+			rotation: rotation,
+			keep: keep,
+			gzip: gzip
+		})
+	}
+
+	pub fn write(&mut self, lines: &str) -> Result<()> {
+		self.write_at(lines, time::get_time().sec)
+	}
+
+	fn write_at(&mut self, lines: &str, now_secs: i64) -> Result<()> {
+		let due = match self.rotation {
+			Rotation::Never => false,
+			Rotation::Size(max) => self.size > 0 && self.size + lines.len() as u64 > max,
+			Rotation::Hourly | Rotation::Daily => self.size > 0 && self.rotation.period(now_secs) != self.period
+		};
+		if due {
+			try!(self.rotate());
+		}
+		if self.size == 0 {
+			self.period = self.rotation.period(now_secs);
+		}
+		try!(self.file.write_all(lines.as_bytes()));
+		self.size += lines.len() as u64;
+		Ok(())
+	}
+
+	//on SIGHUP, the file may have been moved away by logrotate
+	pub fn reopen(&mut self) -> Result<()> {
+		let (file, size, period) = try!(open_append(&self.path, &self.rotation));
+		self.file = file;
+		self.size = size;
+		self.period = period;
+		Ok(())
+	}
+
+	//file.N is dropped, file.1 .. file.N-1 move up by one and the current file becomes file.1
+	fn rotate(&mut self) -> Result<()> {
+		if self.keep == 0 {
+			try!(fs::remove_file(&self.path));
+		}
+		else {
+			let _ = fs::remove_file(self.rotated(self.keep));
+			for number in (1..self.keep).rev() {
+				let from = self.rotated(number);
+				if from.exists() {
+					try!(fs::rename(&from, self.rotated(number + 1)));
+				}
+			}
+			if self.gzip {
+				try!(compress(&self.path, &self.rotated(1)));
+				try!(fs::remove_file(&self.path));
+			}
+			else {
+				try!(fs::rename(&self.path, self.rotated(1)));
+			}
+		}
+		self.reopen()
+	}
+
+	fn rotated(&self, number: usize) -> PathBuf {
+		let mut name = self.path.clone().into_os_string();
+		name.push(format!(".{}", number));
+		if self.gzip {
+			name.push(".gz");
+		}
+		PathBuf::from(name)
+	}
+}
+
+//the file, its size and the period of its last change
+fn open_append(path: &Path, rotation: &Rotation) -> Result<(File, u64, i64)> {
+	let file = try!(OpenOptions::new().create(true).append(true).open(path));
+	let metadata = try!(file.metadata());
+	let modified = metadata.modified().ok()
+		.and_then(|modified| modified.duration_since(::std::time::UNIX_EPOCH).ok())
+		.map(|age| age.as_secs() as i64)
+		.unwrap_or(time::get_time().sec);
+	Ok((file, metadata.len(), rotation.period(modified)))
+}
+
+fn compress(from: &Path, to: &Path) -> Result<()> {
+	let mut input = try!(File::open(from));
+	let output = try!(File::create(to));
+	let mut encoder = GzEncoder::new(output, Compression::default());
+	try!(io::copy(&mut input, &mut encoder));
+	try!(encoder.finish());
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use flate2::read::GzDecoder;
+
+	use std::io::Read;
+	use fsutils::read_file;
+
+	fn temp_dir(test: &str) -> PathBuf {
+		let dir = env::temp_dir().join(format!("run-info-logfile-{}-{}", test, ::std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	#[test]
+	fn rotation_values() {
+		match Rotation::from_str("10M") { Some(Rotation::Size(v)) => assert_eq!(v, 10 * 1024 * 1024), _ => panic!("10M") }
+		match Rotation::from_str("500k") { Some(Rotation::Size(v)) => assert_eq!(v, 500 * 1024), _ => panic!("500k") }
+		match Rotation::from_str("4096") { Some(Rotation::Size(v)) => assert_eq!(v, 4096), _ => panic!("4096") }
+		match Rotation::from_str("daily") { Some(Rotation::Daily) => {}, _ => panic!("daily") }
+		for value in &["", "M", "0", "10X", "weekly", "-1K"] {
+			assert!(Rotation::from_str(value).is_none(), "{}", value);
+		}
+	}
+
+	#[test]
+	fn rotates_by_size() {
+		let dir = temp_dir("size");
+		let path = dir.join("run-info.log");
+		let mut log = LogFile::open(&path, Rotation::Size(10), 2, false).unwrap();
+		for line in &["one 1\n", "two 2\n", "three\n", "four\n"] {
+			log.write(line).unwrap();
+		}
+		//each line would exceed 10 bytes together with the previous one
+		assert_eq!(read_file(&path).unwrap(), "four\n");
+		assert_eq!(read_file(dir.join("run-info.log.1")).unwrap(), "three\n");
+		assert_eq!(read_file(dir.join("run-info.log.2")).unwrap(), "two 2\n");
+		assert!(!dir.join("run-info.log.3").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn rotates_by_day_and_gzips() {
+		let dir = temp_dir("daily");
+		let path = dir.join("run-info.log");
+		let mut log = LogFile::open(&path, Rotation::Daily, 5, true).unwrap();
+		log.write_at("monday\n", 86400 * 3 + 100).unwrap();
+		log.write_at("monday evening\n", 86400 * 4 - 1).unwrap();
+		log.write_at("tuesday\n", 86400 * 4).unwrap();
+		assert_eq!(read_file(&path).unwrap(), "tuesday\n");
+		let mut decoder = GzDecoder::new(File::open(dir.join("run-info.log.1.gz")).unwrap());
+		let mut rotated = String::new();
+		decoder.read_to_string(&mut rotated).unwrap();
+		assert_eq!(rotated, "monday\nmonday evening\n");
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn reopens_a_moved_file() {
+		let dir = temp_dir("reopen");
+		let path = dir.join("run-info.log");
+		let mut log = LogFile::open(&path, Rotation::Never, 5, false).unwrap();
+		log.write("before\n").unwrap();
+		fs::rename(&path, dir.join("moved.log")).unwrap();
+		log.reopen().unwrap();
+		log.write("after\n").unwrap();
+		assert_eq!(read_file(&path).unwrap(), "after\n");
+		assert_eq!(read_file(dir.join("moved.log")).unwrap(), "before\n");
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
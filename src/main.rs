@@ -9,38 +9,141 @@ extern crate term;
 
 extern crate clap;
 use clap::{
-    Arg, App
+    Arg, App, ArgMatches
 };
 
 extern crate time;
 
+extern crate libc;
+
+extern crate regex;
+use regex::Regex;
+
+extern crate flate2;
+
+extern crate toml;
+
+extern crate unicode_width;
+
 use std::mem;
-use std::thread;
+use std::io::{Stdout, Write};
 
+mod fsutils;
 mod graph;
+mod history;
 mod meminfo;
 mod cpuinfo;
+mod cgroup;
 mod printutils;
+mod pressure;
+mod numa;
+mod vmstat;
+mod power;
+mod process;
+mod proctable;
+mod users;
+mod input;
 mod printer;
+mod session;
+mod signals;
+mod export;
+mod logfile;
+mod daemon;
+mod timestamp;
+mod config;
+mod theme;
+mod screen;
+mod widgets;
 
 use cpuinfo::CPUInfo;
 use meminfo::MemInfo;
-use graph::Graph;
+use cgroup::CGroupInfo;
+use pressure::PressureInfo;
+use numa::NumaInfo;
+use vmstat::VmStat;
+use power::PowerInfo;
+use process::ProcessInfo;
+use proctable::{ProcessTable, ProcessView};
+use input::{Input, Key};
+use printer::{Sections, Section, ProcessList};
+use history::{History, Zoom};
+use session::Session;
+use export::{Format, Sink};
+use logfile::{LogFile, Rotation};
+use daemon::PidFile;
+use timestamp::Timestamp;
+use config::Config;
+use printutils::{Thresholds, DEFAULT_THRESHOLDS};
+use theme::{Color, Depth, Theme};
+use screen::Screen;
+use widgets::{Constraint, GRAPH_HEIGHT};
 
 //Holds CLAP arguments
 pub struct Settings {
 	delay: usize,
 	enable_color: bool,
 	enable_graph: bool,
+	json_summary: bool,
+	container: bool,
+	numa: bool,
+	users: bool,
+	services: bool,
+	pressure: bool,
+	vmstat: bool,
+	power: bool,
+	filter: Option<Regex>,
+	pids: Vec<i32>,
+	threads: bool,
+	format: Format,
+	target: Option<String>,
+	prefix: String, //of the StatsD/Graphite metric names, with the hostname filled in
+	output: Option<String>, //log file, used instead of stdout or the target
+	rotation: Rotation,
+	keep: usize,
+	gzip: bool,
+	daemon: bool,
+	pid_file: Option<String>,
+	timestamp: Timestamp,
+	utc: bool,
+	bar_width: usize,
+	graph_height: usize,
+	layout: Vec<Vec<(Section, Constraint)>>, //rows of sections side by side, empty for the default layout
+	collapsed: Vec<Section>, //shown as their header only, after a click on it
+	thresholds: Option<Thresholds>, //bars turn yellow/red above these
+	gradient: bool,
+	theme: Theme,
+	color_depth: Depth,
+	zoom: Zoom,
 	mode: Mode
 }
 
 pub enum Mode {
     Normal,
     Log,
-    Small
+    Small,
+    Process,
+    Processes,
+    Tree,
+    Export //--format other than text, no terminal UI
+}
+
+impl Mode {
+	//the modes that can be chosen in the config file
+	fn from_str(name: &str) -> Option<Mode> {
+		match name {
+			"normal" => Some(Mode::Normal),
+			"small" => Some(Mode::Small),
+			"log" => Some(Mode::Log),
+			"processes" => Some(Mode::Processes),
+			"tree" => Some(Mode::Tree),
+			_ => None
+		}
+	}
 }
 
+//the optional sections that can be listed in the config file
+const SECTIONS: [&'static str; 7] = ["container", "numa", "users", "services", "pressure", "vmstat", "power"];
+
 fn main() {
 	let matches = App::new("run-info")
 						.version("0.5.1")
@@ -68,80 +171,654 @@ fn main() {
 							.short("g")
 							.long("no-graph")
 							.help("Hides the graph displayed under the CPU section in normal mode"))
+						.arg(Arg::with_name("zoom")
+							.short("z")
+							.long("zoom")
+							.help("Sets the time span of the graph: minute, hour or day")
+							.takes_value(true))
+						.arg(Arg::with_name("container")
+							.long("container")
+							.help("Shows the usage of the own cgroup (v2) even when no container is detected"))
+						.arg(Arg::with_name("numa")
+							.long("numa")
+							.help("Shows the NUMA section even if the machine only has one node"))
+						.arg(Arg::with_name("users")
+							.short("u")
+							.long("users")
+							.help("Shows the CPU and memory usage summed up by user"))
+						.arg(Arg::with_name("services")
+							.long("services")
+							.help("Shows the CPU and memory usage summed up by systemd unit or cgroup"))
+						.arg(Arg::with_name("filter")
+							.long("filter")
+							.help("Only counts the processes whose name, command line, user or cgroup match the regex")
+							.takes_value(true))
+						.arg(Arg::with_name("pid")
+							.short("p")
+							.long("pid")
+							.help("Follows a single process instead of the whole machine (can be repeated)")
+							.takes_value(true)
+							.multiple(true)
+							.number_of_values(1)
+							.conflicts_with("log-mode")
+							.conflicts_with("small-mode"))
+						.arg(Arg::with_name("threads")
+							.short("T")
+							.long("threads")
+							.help("Shows the threads of the processes followed with --pid")
+							.requires("pid"))
+						.arg(Arg::with_name("processes")
+							.short("P")
+							.long("processes")
+							.help("Lists all processes, the busiest first")
+							.conflicts_with("log-mode")
+							.conflicts_with("small-mode")
+							.conflicts_with("pid"))
+						.arg(Arg::with_name("tree")
+							.short("t")
+							.long("tree")
+							.help("Shows all processes as a tree, with the usage of each subtree")
+							.conflicts_with("log-mode")
+							.conflicts_with("small-mode")
+							.conflicts_with("pid")
+							.conflicts_with("processes"))
+						.arg(Arg::with_name("json-summary")
+							.short("j")
+							.long("json-summary")
+							.help("Prints the session summary on exit as JSON"))
+						.arg(Arg::with_name("format")
+							.long("format")
+							.help("Writes the measurements for metric collectors instead: text (default), influx, statsd or graphite")
+							.takes_value(true)
+							.conflicts_with("pid")
+							.conflicts_with("processes")
+							.conflicts_with("tree")
+							.conflicts_with("small-mode"))
+						.arg(Arg::with_name("target")
+							.long("target")
+							.help("Sends the --format output to udp://host:port or tcp://host:port instead of stdout")
+							.takes_value(true))
+						.arg(Arg::with_name("prefix")
+							.long("prefix")
+							.help("Prefix of the StatsD/Graphite metric names, {host} is replaced by the hostname")
+							.takes_value(true))
+						.arg(Arg::with_name("output")
+							.short("o")
+							.long("output")
+							.help("Appends the log lines (or the --format output) to a file, which is reopened on SIGHUP")
+							.takes_value(true)
+							.conflicts_with("target")
+							.conflicts_with("pid")
+							.conflicts_with("processes")
+							.conflicts_with("tree")
+							.conflicts_with("small-mode"))
+						.arg(Arg::with_name("rotate")
+							.long("rotate")
+							.help("Rotates the --output file by size (like 10M or 1G), hourly or daily")
+							.takes_value(true))
+						.arg(Arg::with_name("keep")
+							.long("keep")
+							.help("Sets the number of rotated files that are kept (default 5)")
+							.takes_value(true))
+						.arg(Arg::with_name("gzip")
+							.long("gzip")
+							.help("Compresses the rotated files"))
+						.arg(Arg::with_name("daemon")
+							.long("daemon")
+							.help("Runs in the background without a terminal, writing to the --output file"))
+						.arg(Arg::with_name("pid-file")
+							.long("pid-file")
+							.help("Writes the process id to a file, which is removed on exit")
+							.takes_value(true))
+						.arg(Arg::with_name("timestamp")
+							.long("timestamp")
+							.help("Sets the timestamp of the log lines and the --format output: iso8601 (default), rfc3339, epoch, epoch-ms or none")
+							.takes_value(true))
+						.arg(Arg::with_name("utc")
+							.long("utc")
+							.help("Writes the timestamps in UTC instead of the local time"))
+						.arg(Arg::with_name("gradient")
+							.long("gradient")
+							.help("Colors each segment of the load bars and the graph from green to yellow to red by its load"))
+						.arg(Arg::with_name("theme")
+							.long("theme")
+							.help("Sets the colors: dark (default), light, solarized, high-contrast or custom (from the config file)")
+							.takes_value(true))
+						.arg(Arg::with_name("config")
+							.long("config")
+							.help("Reads the settings from this file instead of ~/.config/run-info/config.toml")
+							.takes_value(true))
+						.arg(Arg::with_name("profile")
+							.long("profile")
+							.help("Uses the settings of a [profiles.<name>] table of the config file")
+							.takes_value(true))
 						.get_matches();
-	let delay_str = matches.value_of("delay").unwrap_or("1500").to_owned();
-	let enable_color = matches.occurrences_of("no-color") == 0;
-    let mut mode = Mode::Normal;
+	match read_settings(&matches) {
+		Ok(settings) => std::process::exit(main_loop(settings)),
+		Err(err) => println!("error: {}", err)
+	}
+}
+
+//the command line arguments take precedence over the config file, which takes precedence over the defaults
+fn read_settings(matches: &ArgMatches) -> Result<Settings, String> {
+	let config = try!(Config::load(matches.value_of("config"), matches.value_of("profile")));
+	let value = |name: &str, key: &str| -> Result<Option<String>, String> {
+		match matches.value_of(name) {
+			Some(v) => Ok(Some(v.to_owned())),
+			None => config.value(key)
+		}
+	};
+
+	let delay_str = try!(value("delay", "delay")).unwrap_or(String::from("1500"));
+	let enable_color = matches.occurrences_of("no-color") == 0 && try!(config.flag("color")).unwrap_or(true);
+	let mut mode = match try!(config.value("mode")) {
+		Some(name) => match Mode::from_str(&name) {
+			Some(v) => v,
+			None => return Err(String::from("mode must be normal, small, log, processes or tree."))
+		},
+		None => Mode::Normal
+	};
     if  matches.occurrences_of("log-mode") > 0 {
         mode = Mode::Log;
     }
     if matches.occurrences_of("small-mode") > 0 {
         mode = Mode::Small;
     }
-	let enable_graph = matches.occurrences_of("no-graph") == 0;
+	let mut pids = Vec::new();
+	for pid_str in matches.values_of("pid").map(|values| values.collect()).unwrap_or(Vec::new()) {
+		match pid_str.parse::<i32>() {
+			Ok(v) => pids.push(v),
+			Err(_) => return Err(String::from("pid argument is not a valid number."))
+		}
+	}
+	if !pids.is_empty() {
+		mode = Mode::Process;
+	}
+	if matches.occurrences_of("processes") > 0 {
+		mode = Mode::Processes;
+	}
+	if matches.occurrences_of("tree") > 0 {
+		mode = Mode::Tree;
+	}
+	let enable_graph = matches.occurrences_of("no-graph") == 0 && try!(config.flag("graph")).unwrap_or(true);
+	let json_summary = matches.occurrences_of("json-summary") > 0 || try!(config.flag("json_summary")).unwrap_or(false);
+	//the sections listed in the config file, or the ones shown by default
+	let sections = try!(config.list("sections"));
+	let section = |name: &str, default: bool| -> Result<bool, String> {
+		match sections {
+			Some(ref sections) => {
+				for section in sections {
+					if !SECTIONS.contains(&section.as_str()) {
+						return Err(format!("unknown section {}, expected one of {}.", section, SECTIONS.join(", ")));
+					}
+				}
+				Ok(sections.iter().any(|section| section == name))
+			},
+			None => Ok(default)
+		}
+	};
+	//the screen layout of the normal mode, its sections are shown even if they are not listed in sections
+	let mut layout = Vec::new();
+	for row in try!(config.list("layout.rows")).unwrap_or(Vec::new()) {
+		layout.push(try!(printer::parse_layout_row(&row)));
+	}
+	let placed = |name: Section| layout.iter().any(|row| row.iter().any(|&(section, _)| section == name));
+	let container = matches.occurrences_of("container") > 0 || placed(Section::Container) || try!(section("container", false));
+	let numa = matches.occurrences_of("numa") > 0 || placed(Section::Numa) || try!(section("numa", false));
+	let users = matches.occurrences_of("users") > 0 || placed(Section::Users) || try!(section("users", false));
+	let services = matches.occurrences_of("services") > 0 || placed(Section::Services) || try!(section("services", false));
+	let pressure = placed(Section::Pressure) || try!(section("pressure", true));
+	let vmstat = placed(Section::VmStat) || try!(section("vmstat", true));
+	let power = placed(Section::Power) || try!(section("power", true));
+	let filter = match try!(value("filter", "filter")).map(|pattern| Regex::new(&pattern)) {
+		Some(Ok(v)) => Some(v),
+		Some(Err(err)) => return Err(format!("filter argument is not a valid regex: {}", err)),
+		None => None
+	};
+	let format = match Format::from_str(&try!(value("format", "format")).unwrap_or(String::from("text"))) {
+		Some(v) => v,
+		None => return Err(String::from("format argument must be text, influx, statsd or graphite."))
+	};
+	let output = try!(value("output", "output"));
+	let exported = match format {
+		Format::Text => output.is_some(),
+		_ => true
+	};
+	//a display mode given on the command line wins over a format or an output file from the config file
+	let display_mode = ["log-mode", "small-mode", "pid", "processes", "tree"].iter()
+		.any(|name| matches.occurrences_of(name) > 0);
+	if exported && (!display_mode || matches.occurrences_of("format") + matches.occurrences_of("output") > 0) {
+		mode = Mode::Export;
+	}
+	let timestamp = match Timestamp::from_str(&try!(value("timestamp", "timestamp")).unwrap_or(String::from("iso8601"))) {
+		Some(v) => v,
+		None => return Err(String::from("timestamp argument must be iso8601, rfc3339, epoch, epoch-ms or none."))
+	};
+	let rotation = match Rotation::from_str(&try!(value("rotate", "rotate")).unwrap_or(String::from("never"))) {
+		Some(v) => v,
+		None => return Err(String::from("rotate argument must be a size (like 10M), hourly or daily."))
+	};
+	let keep = match try!(value("keep", "keep")).unwrap_or(String::from("5")).parse::<usize>() {
+		Ok(v) => v,
+		Err(_) => return Err(String::from("keep argument is not a valid number."))
+	};
+	let zoom = match Zoom::from_str(&try!(value("zoom", "zoom")).unwrap_or(String::from("minute"))) {
+		Some(v) => v,
+		None => return Err(String::from("zoom argument must be minute, hour or day."))
+	};
 	let delay = match delay_str.parse::<usize>() {
 		Ok(v) => v,
-		Err(_) => {
-            println!("error: delay argument is not a valid number.");
-            return;
-        }
+		Err(_) => return Err(String::from("delay argument is not a valid number."))
+	};
+	let bar_width = match try!(config.value("bar_width")).unwrap_or(String::from("40")).parse::<usize>() {
+		Ok(v) if v >= 10 => v,
+		_ => return Err(String::from("bar_width must be a number of at least 10."))
+	};
+	let graph_height = match try!(config.value("layout.graph_height")).map(|height| height.parse::<usize>()) {
+		Some(Ok(v)) if v >= 2 => v,
+		Some(_) => return Err(String::from("graph_height must be a number of at least 2.")),
+		None => GRAPH_HEIGHT
+	};
+	let warning = try!(config.number("thresholds.warning"));
+	let critical = try!(config.number("thresholds.critical"));
+	let thresholds = match (warning, critical) {
+		(None, None) => None,
+		(warning, critical) => {
+			let thresholds = Thresholds {
+				warning: warning.map(|warning| warning / 100.0).unwrap_or(DEFAULT_THRESHOLDS.warning),
+				critical: critical.map(|critical| critical / 100.0).unwrap_or(DEFAULT_THRESHOLDS.critical)
+			};
+			if thresholds.warning < 0.0 || thresholds.warning > thresholds.critical || thresholds.critical > 1.0 {
+				return Err(String::from("thresholds must be percentages with warning <= critical."));
+			}
+			Some(thresholds)
+		}
+	};
+	let mut theme = match Theme::from_str(&try!(value("theme", "theme")).unwrap_or(String::from("dark"))) {
+		Some(v) => v,
+		None => return Err(String::from("theme argument must be dark, light, solarized, high-contrast or custom."))
+	};
+	//the [colors] table changes single colors of any theme
+	for role in &["highlight", "header", "graph", "red", "green", "yellow"] {
+		if let Some(value) = try!(config.value(&format!("colors.{}", role))) {
+			match Color::from_str(&value) {
+				Some(color) => { theme.set(role, color); },
+				None => return Err(format!("colors.{} must be \"#rrggbb\", a number from 0 to 255 or a color name.", role))
+			}
+		}
+	}
+	let color_depth = match try!(config.value("color_depth")) {
+		Some(name) => match Depth::from_str(&name) {
+			Some(v) => v,
+			None => return Err(String::from("color_depth must be 16, 256 or truecolor."))
+		},
+		None => Depth::detect()
 	};
-	let settings = Settings {
+	//StatsD and Graphite have well-known local ports, influx is written to stdout
+	let target = try!(value("target", "target")).or(format.default_target().map(|target| target.to_owned()));
+	let prefix = try!(value("prefix", "prefix")).unwrap_or(String::from("run-info.{host}"));
+	let daemon = matches.occurrences_of("daemon") > 0;
+	if daemon && output.is_none() {
+		return Err(String::from("daemon mode needs an output file."));
+	}
+	Ok(Settings {
 		delay: delay,
 		enable_color: enable_color,
 		enable_graph: enable_graph,
+		json_summary: json_summary,
+		container: container,
+		numa: numa,
+		users: users,
+		services: services,
+		pressure: pressure,
+		vmstat: vmstat,
+		power: power,
+		filter: filter,
+		pids: pids,
+		threads: matches.occurrences_of("threads") > 0,
+		format: format,
+		target: target,
+		prefix: export::expand_prefix(&prefix),
+		output: output,
+		rotation: rotation,
+		keep: keep,
+		gzip: matches.occurrences_of("gzip") > 0 || try!(config.flag("gzip")).unwrap_or(false),
+		daemon: daemon,
+		pid_file: try!(value("pid-file", "pid_file")),
+		timestamp: timestamp,
+		utc: matches.occurrences_of("utc") > 0 || try!(config.flag("utc")).unwrap_or(false),
+		bar_width: bar_width,
+		graph_height: graph_height,
+		layout: layout,
+		collapsed: Vec::new(),
+		thresholds: thresholds,
+		gradient: matches.occurrences_of("gradient") > 0 || try!(config.flag("gradient")).unwrap_or(false),
+		theme: theme,
+		color_depth: color_depth,
+		zoom: zoom,
 	    mode: mode
-	};
-	main_loop(settings);
+	})
 }
 
 #[allow(unused_assignments)]
-fn main_loop(settings: Settings) {
-	println!("");
-	let mut term = term::stdout().expect("term is not available.");
+//returns the exit code
+fn main_loop(mut settings: Settings) -> i32 {
+	//the sink and the pid file are opened before detaching, so errors are still shown
+	let mut sink = match settings.mode {
+		Mode::Export => match open_sink(&settings) {
+			Ok(v) => Some(v),
+			Err(err) => {
+				eprintln!("error: output is not available: {}", err);
+				return 1;
+			}
+		},
+		_ => None
+	};
+	let pid_file = match settings.pid_file {
+		Some(ref path) => match PidFile::create(path) {
+			Ok(v) => Some(v),
+			Err(err) => {
+				eprintln!("error: pid file can not be written: {}", err);
+				return 1;
+			}
+		},
+		None => None
+	};
+	if settings.daemon {
+		if let Err(err) = daemon::detach() {
+			eprintln!("error: can not run in the background: {}", err);
+			return 1;
+		}
+		if let Some(ref pid_file) = pid_file {
+			let _ = pid_file.write();
+		}
+	}
+	//the screen modes draw on the alternate screen and the log lines go to stdout as they are
+	//the output of --format belongs to the collector, no terminal is needed (or available to a daemon) for it
+	let mut term: Option<Box<term::Terminal<Output=Stdout> + Send>> = match settings.mode {
+		Mode::Export => None,
+		Mode::Log => {
+			println!("");
+			Some(term::stdout().expect("term is not available."))
+		},
+		_ => Some(Box::new(Screen::new(input::is_terminal())))
+	};
 	let mut meminfo = MemInfo::new();
 	let mut cpuinfo_old = CPUInfo::new();
 	let mut cpuinfo_new = CPUInfo::new();
 	let mut cpuinfo_delta = CPUInfo::new(); //The delta between the two time frames
 	let _ = cpuinfo_new.update();
 
-	let mut cpu_graph = Graph::new();
+	let mut history = History::new();
+	let mut cgroup = None;
+	if settings.container || cgroup::in_container() {
+		cgroup = CGroupInfo::detect(cgroup::CGROUP_ROOT);
+	}
+	//dropped if the kernel does not support PSI, if there is only one NUMA node ... or if hidden in the config file
+	let mut pressure = if settings.pressure { Some(PressureInfo::new(pressure::PRESSURE_ROOT)) } else { None };
+	let mut numa = Some(NumaInfo::new(numa::NODE_ROOT));
+	let mut vmstat = if settings.vmstat { Some(VmStat::new()) } else { None };
+	let mut power = if settings.power { Some(PowerInfo::new(power::POWER_SUPPLY_ROOT)) } else { None };
+	let mut processes: Vec<ProcessInfo> = settings.pids.iter()
+		.map(|pid| ProcessInfo::new(*pid))
+		.collect();
+	let mut process_table = ProcessTable::new();
+	process_table.filter = settings.filter.clone();
+	let mut process_view = ProcessView::new();
+	let mut session = Session::new();
+	let mut failure = None; //shown after the terminal is restored
+
+	signals::install(settings.output.is_some() || settings.daemon);
+	//log mode is often piped somewhere, so it does not touch the terminal
+	let mut input = match settings.mode {
+		Mode::Log | Mode::Export => None,
+		_ => Some(Input::new())
+	};
+	let mut next_update = 0; //in ns, key presses only redraw the screen until then
+
+	while !signals::terminate_requested() {
+		if signals::reload_requested() {
+			if let Some(ref mut sink) = sink {
+				let _ = sink.reopen();
+			}
+		}
+		if time::precise_time_ns() >= next_update {
+			next_update = time::precise_time_ns() + settings.delay as u64 * 1000000;
+
+			match meminfo.update() {  //we can just update the meminfo
+				Ok(_) => {},
+				Err(_) => {
+					failure = Some("Memory information is not available.");
+					break;}
+			};
+
+			//the new info is becoming the old info, and a new info is requested
+			mem::swap(&mut cpuinfo_new, &mut cpuinfo_old);
+			cpuinfo_new = CPUInfo::new();
+			match cpuinfo_new.update() {
+				Ok(_) => {},
+				Err(_) => {
+					failure = Some("CPU information is not available.");
+					break;
+				}
+			};
+			cpuinfo_delta = CPUInfo::new(); //reset delta
+			CPUInfo::calculate_delta(&mut cpuinfo_delta, &cpuinfo_old, &cpuinfo_new); //calculate the difference
+			//the section disappears if the cgroup can not be read anymore
+			let cgroup_failed = match cgroup {
+				Some(ref mut cgroup) => cgroup.update(cpuinfo_new.cores).is_err(),
+				None => false
+			};
+			if cgroup_failed {
+				cgroup = None;
+			}
+			let pressure_failed = match pressure {
+				Some(ref mut pressure) => pressure.update().is_err(),
+				None => false
+			};
+			if pressure_failed {
+				pressure = None;
+			}
+			if let Some(ref pressure) = pressure {
+				history.record_pressure(pressure);
+			}
+			let numa_failed = match numa {
+				Some(ref mut numa) => numa.update().is_err() || (numa.nodes.len() < 2 && !settings.numa),
+				None => false
+			};
+			if numa_failed {
+				numa = None;
+			}
+			let vmstat_failed = match vmstat {
+				Some(ref mut vmstat) => vmstat.update().is_err(),
+				None => false
+			};
+			if vmstat_failed {
+				vmstat = None;
+			}
+			let power_failed = match power {
+				Some(ref mut power) => power.update().is_err(),
+				None => false
+			};
+			if power_failed {
+				power = None;
+			}
+			for process in &mut processes {
+				let _ = process.update(cpuinfo_new.cores); //exited processes are kept on screen
+				if settings.threads {
+					let _ = process.update_threads();
+				}
+			}
+			match settings.mode {
+				Mode::Processes | Mode::Tree => { let _ = process_table.update(); },
+				_ if settings.users || settings.services || settings.filter.is_some() => { let _ = process_table.update(); },
+				_ => {}
+			}
+			session.record(&cpuinfo_delta, &meminfo);
+			history.record(&cpuinfo_delta, &meminfo);
+		}
 
-	loop {
-		match meminfo.update() {  //we can just update the meminfo
-			Ok(_) => {},
-			Err(_) => {
-				println!("error: Memory information is not available.");
-				println!("maybe you are not running this program on a Linux OS?");
-				break;}
+		let users = if settings.users { Some(process_table.by_user()) } else { None };
+		let matching = if settings.filter.is_some() { Some(vec![process_table.totals()]) } else { None };
+		let services = if settings.services { Some(process_table.by_service()) } else { None };
+		let sections = Sections {
+			cgroup: cgroup.as_ref(),
+			pressure: pressure.as_ref(),
+			numa: numa.as_ref(),
+			vmstat: vmstat.as_ref(),
+			power: power.as_ref(),
+			users: users.as_ref().map(|groups| groups.as_slice()),
+			services: services.as_ref().map(|groups| groups.as_slice()),
+			matching: matching.as_ref().map(|groups| groups.as_slice())
+		};
+		let rows = match settings.mode {
+			Mode::Processes => process_table.flat_rows(),
+			Mode::Tree => process_table.tree_rows(&process_view.collapsed),
+			_ => Vec::new()
 		};
 
-		//the new info is becoming the old info, and a new info is requested
-        mem::swap(&mut cpuinfo_new, &mut cpuinfo_old);
-        cpuinfo_new = CPUInfo::new();
-		match cpuinfo_new.update() {
-			Ok(_) => {},
-			Err(_) => {
-				println!("error: CPU information is not available.");
-				println!("maybe you are not running this program on a Linux OS?");
-				break;
+		match term {
+			None => send_measurements(&mut sink, &settings, &cpuinfo_delta, &meminfo, &sections),
+			Some(ref mut term) => {
+				match settings.mode {
+                Mode::Normal => {
+                    printer::print(term, &settings, &cpuinfo_delta, &meminfo, &sections, &history)
+                },
+                Mode::Log => {
+                    printer::print_log_mode(term, &settings, &cpuinfo_delta, &meminfo, &sections)
+                },
+		        Mode::Small => {
+                    printer::print_small_mode(term, &settings, &cpuinfo_delta, &meminfo, &sections, &history)
+                },
+                Mode::Process => {
+                    printer::print_process_mode(term, &settings, &cpuinfo_delta, &meminfo, &processes)
+                },
+                Mode::Processes | Mode::Tree => {
+                    printer::print_process_table(term, &settings, &cpuinfo_delta, &meminfo, ProcessList {
+                        rows: &rows,
+                        totals: process_table.totals(),
+                        filtered: process_table.filter.is_some(),
+                        view: &mut process_view
+                    })
+                },
+                Mode::Export => {} //has no terminal
+				}
+				//shows the frame
+				let _ = term.flush();
+			}
+		}
+
+		//wait until the next update or the next key press
+		let remaining = next_update.saturating_sub(time::precise_time_ns()) / 1000000;
+		let keys = match input {
+			Some(ref mut input) => input.wait(remaining),
+			None => {
+				signals::sleep(remaining);
+				Vec::new()
 			}
 		};
-		cpuinfo_delta = CPUInfo::new(); //reset delta
-		CPUInfo::calculate_delta(&mut cpuinfo_delta, &cpuinfo_old, &cpuinfo_new); //calculate the difference
-
-		match settings.mode {
-            Mode::Normal => {
-                printer::print(&mut term, &settings, &cpuinfo_delta, &meminfo, &mut cpu_graph)
-            },
-            Mode::Log => {
-                printer::print_log_mode(&mut term, &settings, &cpuinfo_delta, &meminfo)
-            },
-		    Mode::Small => {
-                printer::print_small_mode(&mut term, &settings, &cpuinfo_delta, &meminfo)
-            }
-		}
-
-		thread::sleep(std::time::Duration::from_millis(settings.delay as u64)); //wait until next update
+		for key in keys {
+			//while a search pattern is typed, every key belongs to it
+			if process_view.search.is_some() {
+				process_view.handle_key(key, &rows, printer::visible_process_rows());
+				continue;
+			}
+			let table = match settings.mode {
+				Mode::Processes | Mode::Tree => true,
+				_ => false
+			};
+			match key {
+				Key::Char('q') => signals::request_terminate(),
+				Key::Char('+') => settings.zoom = settings.zoom.zoom_in(),
+				Key::Char('-') => settings.zoom = settings.zoom.zoom_out(),
+				//the wheel scrolls the process list and zooms the graph everywhere else
+				Key::ScrollUp | Key::ScrollDown if table => {
+					process_view.scroll_by(&rows, printer::visible_process_rows(), key == Key::ScrollDown);
+				},
+				Key::ScrollUp => settings.zoom = settings.zoom.zoom_in(),
+				Key::ScrollDown => settings.zoom = settings.zoom.zoom_out(),
+				Key::Click(line, _) if table => {
+					if let Some(index) = printer::process_row_at(line, process_view.scroll) {
+						process_view.click(&rows, index);
+					}
+				},
+				Key::Click(line, column) => {
+					if let Mode::Normal = settings.mode {
+						let clicked = printer::header_at(&settings, &cpuinfo_delta, &meminfo, &sections, &history, line, column);
+						if let Some(section) = clicked {
+							match settings.collapsed.iter().position(|collapsed| *collapsed == section) {
+								Some(index) => { settings.collapsed.remove(index); },
+								None => settings.collapsed.push(section)
+							}
+						}
+					}
+				},
+				_ if table => { process_view.handle_key(key, &rows, printer::visible_process_rows()); },
+				_ => {}
+			}
+		}
+		if let Some(filter) = process_view.take_filter() {
+			process_table.filter = filter;
+		}
+	}
+
+	//restore the terminal: the screen is left (and the mouse released) when it is dropped, before echo is on again
+	//the summary goes below the previous output
+	if term.take().is_none() {
+		//without a terminal the error goes to stderr, and into the log file when detached from it
+		if let Some(failure) = failure {
+			eprintln!("error: {}", failure);
+			if settings.daemon {
+				if let Some(ref mut sink) = sink {
+					let _ = sink.send(&format!("error: {}\n", failure));
+				}
+			}
+			return 1;
+		}
+		return 0;
+	}
+	if let Some(ref mut input) = input {
+		input.restore();
+	}
+	if let Some(failure) = failure {
+		println!("error: {}", failure);
+		println!("maybe you are not running this program on a Linux OS?");
+	}
+	let mut term = term::stdout().expect("term is not available.");
+	if session.samples() > 0 {
+		if settings.json_summary {
+			let _ = writeln!(term, "{}", session.to_json());
+		}
+		else {
+			printer::print_summary(&mut term, &settings, &session);
+		}
+	}
+	if failure.is_some() { 1 } else { 0 }
+}
+
+//the --output file, the --target or stdout
+fn open_sink(settings: &Settings) -> std::io::Result<Sink> {
+	match settings.output {
+		Some(ref path) => LogFile::open(path, settings.rotation, settings.keep, settings.gzip).map(Sink::File),
+		None => Sink::open(settings.target.as_ref().map(|target| target.as_str()))
+	}
+}
+
+//the first delta spans no time and is not sent, a failed send is retried on the next tick
+fn send_measurements(sink: &mut Option<Sink>, settings: &Settings, cpu: &CPUInfo, mem: &MemInfo,
+                     sections: &Sections) {
+	if cpu.total_load.busy + cpu.total_load.idle == 0 {
+		return;
+	}
+	//StatsD has no timestamps and Graphite always takes seconds
+	let now = time::get_time();
+	let lines = match settings.format {
+		Format::Text => printer::log_line(settings, cpu, mem, sections),
+		Format::Influx => export::influx_lines(cpu, mem, settings.timestamp.influx(now)),
+		Format::StatsD => export::statsd_lines(cpu, mem, &settings.prefix),
+		Format::Graphite => export::graphite_lines(cpu, mem, &settings.prefix, now.sec as u64)
+	};
+	if let Some(ref mut sink) = *sink {
+		let _ = sink.send(&lines);
 	}
 }
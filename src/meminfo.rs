@@ -3,10 +3,10 @@ Reads key/value pairs from /proc/meminfo
 */
 
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
 use std::io::Result;
 
+use fsutils::read_file;
+
 pub struct MemInfo {
 	pub total: u64,
 	pub free: u64,
@@ -78,13 +78,9 @@ impl MemInfo {
 	}
 
 	pub fn swap_use(&self) -> f64 {
+		if self.swap_total == 0 { //machines without swap
+			return 0.0;
+		}
 		(self.swap_total - self.swap_free) as f64 / self.swap_total as f64
 	}
 }
-
-fn read_file(location: &str) -> Result<String> {
-	let mut file = try!(File::open(location));
-	let mut ret_val = String::new();
-	try!(file.read_to_string(&mut ret_val));
-	Ok(ret_val)
-}
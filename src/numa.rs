@@ -0,0 +1,251 @@
+/*
+Reads the NUMA topology and per-node memory from /sys/devices/system/node
+The cpu load of a node is summed up from the cores in its cpulist
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+use cpuinfo::{CPUInfo, CPULoad};
+use fsutils::{read_file, read_keyed, counter_delta};
+
+pub const NODE_ROOT: &'static str = "/sys/devices/system/node";
+
+pub struct NumaNode {
+	pub id: usize,
+	pub cpus: Vec<usize>,
+
+	//in bytes, used is calculated like in MemInfo (without the page cache)
+	pub mem_total: u64,
+	pub mem_free: u64,
+	pub mem_used: u64,
+
+	//numastat counters since the last update (in pages)
+	pub numa_hit: u64, //allocated on this node as intended
+	pub numa_miss: u64, //allocated on this node, although another one was preferred
+	pub numa_foreign: u64, //intended for this node, but allocated elsewhere
+
+	last_counters: HashMap<String, u64>
+}
+
+pub struct NumaInfo {
+	root: PathBuf,
+	pub nodes: Vec<NumaNode>
+}
+
+impl NumaInfo {
+	//root is usually NODE_ROOT, but can point to any tree with the same layout
+	pub fn new<P: AsRef<Path>>(root: P) -> NumaInfo {
+		NumaInfo {
+			root: root.as_ref().to_path_buf(),
+			nodes: Vec::new()
+		}
+	}
+
+	pub fn update(&mut self) -> Result<()> {
+		let mut ids = Vec::new();
+		for entry in try!(fs::read_dir(&self.root)) {
+			let name = try!(entry).file_name().to_string_lossy().into_owned();
+			if name.starts_with("node") {
+				if let Ok(id) = name[4..].parse::<usize>() {
+					ids.push(id);
+				}
+			}
+		}
+		ids.sort();
+
+		//nodes can go away with memory hotplug, the counters of the remaining ones are kept
+		self.nodes.retain(|node| ids.contains(&node.id));
+		for id in ids {
+			if !self.nodes.iter().any(|node| node.id == id) {
+				self.nodes.push(NumaNode::new(id));
+			}
+		}
+		self.nodes.sort_by_key(|node| node.id);
+		for node in &mut self.nodes {
+			try!(node.update(&self.root));
+		}
+		Ok(())
+	}
+
+	//sums the (delta) load of the cores belonging to the node
+	pub fn node_load(node: &NumaNode, cpu: &CPUInfo) -> CPULoad {
+		let mut load = CPULoad {busy: 0, idle: 0};
+		for (core_load, core_id) in cpu.cores_load.iter().zip(cpu.core_ids.iter()) {
+			if node.cpus.contains(core_id) {
+				load.busy += core_load.busy;
+				load.idle += core_load.idle;
+			}
+		}
+		load
+	}
+}
+
+impl NumaNode {
+	fn new(id: usize) -> NumaNode {
+		NumaNode {
+			id: id,
+			cpus: Vec::new(),
+			mem_total: 0,
+			mem_free: 0,
+			mem_used: 0,
+			numa_hit: 0,
+			numa_miss: 0,
+			numa_foreign: 0,
+			last_counters: HashMap::new()
+		}
+	}
+
+	fn update(&mut self, root: &Path) -> Result<()> {
+		let dir = root.join(format!("node{}", self.id));
+		self.cpus = parse_cpulist(&try!(read_file(dir.join("cpulist"))));
+
+		let mem_map = parse_node_meminfo(&try!(read_file(dir.join("meminfo"))));
+		self.mem_total = mem_map.get("MemTotal").cloned().unwrap_or(0);
+		self.mem_free = mem_map.get("MemFree").cloned().unwrap_or(0);
+		let file_pages = mem_map.get("FilePages").cloned().unwrap_or(0);
+		self.mem_used = self.mem_total.saturating_sub(self.mem_free + file_pages);
+
+		let counters = try!(read_keyed(dir.join("numastat")));
+		if !self.last_counters.is_empty() {
+			self.numa_hit = counter_delta(&counters, &self.last_counters, "numa_hit");
+			self.numa_miss = counter_delta(&counters, &self.last_counters, "numa_miss");
+			self.numa_foreign = counter_delta(&counters, &self.last_counters, "numa_foreign");
+		}
+		self.last_counters = counters;
+		Ok(())
+	}
+
+	pub fn memory_use(&self) -> f64 {
+		if self.mem_total == 0 {
+			return 0.0;
+		}
+		self.mem_used as f64 / self.mem_total as f64
+	}
+}
+
+//lines look like "Node 0 MemTotal:       16384 kB", the values are returned in bytes
+fn parse_node_meminfo(plain: &str) -> HashMap<String, u64> {
+	let mut mem_map = HashMap::new();
+	for line in plain.lines() {
+		let columns: Vec<&str> = line.split_whitespace().collect();
+		if columns.len() >= 4 {
+			let mut value = columns[3].parse::<u64>().expect("incorrect node memory information format");
+			if columns.len() >= 5 {
+				value *= 1024;
+			}
+			mem_map.insert(columns[2].trim_end_matches(':').to_owned(), value);
+		}
+	}
+	mem_map
+}
+
+//parses cpu lists like "0-3,8-11"
+pub fn parse_cpulist(list: &str) -> Vec<usize> {
+	let mut cpus = Vec::new();
+	for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+		let mut bounds = range.splitn(2, '-');
+		let from = bounds.next().unwrap_or("").parse::<usize>().expect("incorrect cpu list format");
+		let to = match bounds.next() {
+			Some(to) => to.parse::<usize>().expect("incorrect cpu list format"),
+			None => from
+		};
+		for cpu in from..to + 1 {
+			cpus.push(cpu);
+		}
+	}
+	cpus
+}
+
+//formats cpu lists back into ranges
+pub fn format_cpulist(cpus: &[usize]) -> String {
+	let mut ranges = Vec::new();
+	let mut index = 0;
+	while index < cpus.len() {
+		let from = cpus[index];
+		while index + 1 < cpus.len() && cpus[index + 1] == cpus[index] + 1 {
+			index += 1;
+		}
+		if cpus[index] == from {
+			ranges.push(format!("{}", from));
+		}
+		else {
+			ranges.push(format!("{}-{}", from, cpus[index]));
+		}
+		index += 1;
+	}
+	ranges.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::env;
+	use std::fs::File;
+	use std::io::Write;
+
+	const MEMINFO: &'static str = "Node 1 MemTotal:       16384 kB\n\
+	                               Node 1 MemFree:         4096 kB\n\
+	                               Node 1 FilePages:       2048 kB\n\
+	                               Node 1 HugePages_Total:     8\n";
+
+	fn write_files(dir: &Path, files: &[(&str, &str)]) {
+		fs::create_dir_all(dir).unwrap();
+		for &(file, content) in files {
+			File::create(dir.join(file)).unwrap().write_all(content.as_bytes()).unwrap();
+		}
+	}
+
+	#[test]
+	fn cpulists() {
+		assert_eq!(parse_cpulist("0-3,8-11\n"), vec![0, 1, 2, 3, 8, 9, 10, 11]);
+		assert_eq!(parse_cpulist("5"), vec![5]);
+		assert_eq!(parse_cpulist("0,2,4-5"), vec![0, 2, 4, 5]);
+		//a node without cpus (only memory) has an empty list
+		assert_eq!(parse_cpulist("\n"), Vec::<usize>::new());
+
+		assert_eq!(format_cpulist(&[0, 1, 2, 3, 8, 9, 10, 11]), "0-3,8-11");
+		assert_eq!(format_cpulist(&[7]), "7");
+		assert_eq!(format_cpulist(&[0, 2, 3]), "0,2-3");
+		assert_eq!(format_cpulist(&[]), "");
+		for list in &["0-3,8-11", "1", "0,2,4-7,9", "0-63"] {
+			assert_eq!(format_cpulist(&parse_cpulist(list)), *list);
+		}
+	}
+
+	#[test]
+	fn node_meminfo() {
+		let mem_map = parse_node_meminfo(MEMINFO);
+		assert_eq!(mem_map.get("MemTotal"), Some(&(16384 * 1024)));
+		assert_eq!(mem_map.get("FilePages"), Some(&(2048 * 1024)));
+		//counts without a unit are kept as they are
+		assert_eq!(mem_map.get("HugePages_Total"), Some(&8));
+	}
+
+	#[test]
+	fn nodes_from_a_tree() {
+		let root = env::temp_dir().join(format!("run-info-numa-{}", ::std::process::id()));
+		let _ = fs::remove_dir_all(&root);
+		write_files(&root.join("node1"), &[("cpulist", "4-7\n"), ("meminfo", MEMINFO),
+			("numastat", "numa_hit 1000\nnuma_miss 10\nnuma_foreign 5\n")]);
+		write_files(&root.join("node0"), &[("cpulist", "0-3\n"), ("meminfo", MEMINFO), ("numastat", "")]);
+		fs::create_dir_all(root.join("power")).unwrap();
+		let mut numa = NumaInfo::new(&root);
+		numa.update().unwrap();
+		assert_eq!(numa.nodes.iter().map(|node| node.id).collect::<Vec<usize>>(), vec![0, 1]);
+		let node = &numa.nodes[1];
+		assert_eq!(node.cpus, vec![4, 5, 6, 7]);
+		assert_eq!(node.mem_used, 10240 * 1024);
+		assert_eq!(node.memory_use(), 0.625);
+		//the counters are deltas from the second update on
+		assert_eq!(node.numa_hit, 0);
+		write_files(&root.join("node1"), &[("numastat", "numa_hit 1600\nnuma_miss 12\nnuma_foreign 5\n")]);
+		numa.update().unwrap();
+		let node = &numa.nodes[1];
+		assert_eq!((node.numa_hit, node.numa_miss, node.numa_foreign), (600, 2, 0));
+		let _ = fs::remove_dir_all(&root);
+	}
+}
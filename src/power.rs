@@ -0,0 +1,287 @@
+/*
+Reads batteries and AC adapters from /sys/class/power_supply
+The time remaining is estimated from the energy samples of the last minutes
+*/
+
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+use time;
+
+use fsutils::read_file;
+use graph::Graph;
+
+pub const POWER_SUPPLY_ROOT: &'static str = "/sys/class/power_supply";
+
+//number of (time, energy) samples the draw is estimated from
+const ENERGY_SAMPLES: usize = 60;
+
+pub struct Battery {
+	pub name: String,
+	pub capacity: f64, //charge, from 0.0 to 1.0
+	pub status: String, //"Charging", "Discharging", "Full", ...
+	pub energy_now: Option<f64>, //in Wh
+	pub energy_full: Option<f64>,
+	pub power_now: Option<f64> //in W, as reported by the battery (always positive)
+}
+
+pub struct PowerInfo {
+	root: PathBuf,
+	pub batteries: Vec<Battery>,
+	pub ac_online: Option<bool>, //None if there is no AC adapter
+	energy: Graph<(f64, f64)> //(seconds, summed energy of all batteries in Wh)
+}
+
+impl PowerInfo {
+	//root is usually POWER_SUPPLY_ROOT, but can point to any tree with the same layout
+	pub fn new<P: AsRef<Path>>(root: P) -> PowerInfo {
+		PowerInfo {
+			root: root.as_ref().to_path_buf(),
+			batteries: Vec::new(),
+			ac_online: None,
+			energy: Graph::new(ENERGY_SAMPLES)
+		}
+	}
+
+	pub fn update(&mut self) -> Result<()> {
+		let mut supplies = Vec::new();
+		for entry in try!(fs::read_dir(&self.root)) {
+			supplies.push(try!(entry).path());
+		}
+		supplies.sort();
+
+		self.batteries.clear();
+		self.ac_online = None;
+		for supply in supplies {
+			let kind = read_trimmed(&supply, "type").unwrap_or(String::new());
+			match kind.as_str() {
+				"Battery" => {
+					if let Some(battery) = Battery::read(&supply) {
+						self.batteries.push(battery);
+					}
+				},
+				"Mains" => {
+					let online = read_trimmed(&supply, "online").map(|v| v == "1").unwrap_or(false);
+					self.ac_online = Some(self.ac_online.unwrap_or(false) || online);
+				},
+				_ => {}
+			}
+		}
+
+		//summing up no batteries at all would give Some(0.0)
+		let energy: Option<f64> = self.batteries.iter().map(|battery| battery.energy_now).sum();
+		if let (Some(energy), false) = (energy, self.batteries.is_empty()) {
+			let now = time::precise_time_ns() as f64 / 1000000000.0;
+			self.energy.push((now, energy));
+		}
+		Ok(())
+	}
+
+	//summed draw of all batteries in W (positive while discharging, negative while charging)
+	//the batteries' own reading is preferred, otherwise the recent energy samples are used
+	pub fn draw(&self) -> Option<f64> {
+		if self.batteries.is_empty() {
+			return None;
+		}
+		let reported: Option<f64> = self.batteries.iter()
+			.map(|battery| battery.power_now.map(|power| if battery.status == "Charging" { -power } else { power }))
+			.sum();
+		if reported.is_some() {
+			return reported;
+		}
+		self.estimated_draw()
+	}
+
+	fn estimated_draw(&self) -> Option<f64> {
+		let (first_time, first_energy) = match self.energy.get(0) {
+			Some(v) => v,
+			None => return None
+		};
+		let (last_time, last_energy) = self.energy.latest().expect("graph can not be empty");
+		if last_time - first_time <= 0.0 {
+			return None;
+		}
+		Some((first_energy - last_energy) / ((last_time - first_time) / 3600.0))
+	}
+
+	//time until the batteries are empty (while discharging) or full (while charging), in seconds
+	pub fn time_remaining(&self) -> Option<f64> {
+		let energy: f64 = self.batteries.iter().filter_map(|battery| battery.energy_now).sum();
+		let full: f64 = self.batteries.iter().filter_map(|battery| battery.energy_full).sum();
+		//the trend of the recent samples is more stable than a single power_now reading
+		let draw = match self.estimated_draw() {
+			Some(v) if v.abs() > 0.01 => v,
+			_ => match self.draw() {
+				Some(v) if v.abs() > 0.01 => v,
+				_ => return None
+			}
+		};
+		if draw > 0.0 {
+			Some(energy / draw * 3600.0)
+		}
+		else if full > energy {
+			Some((full - energy) / -draw * 3600.0)
+		}
+		else {
+			None
+		}
+	}
+
+	pub fn charging(&self) -> bool {
+		self.batteries.iter().any(|battery| battery.status == "Charging")
+	}
+
+	//mean charge of all batteries, weighted by their size if it is known
+	pub fn capacity(&self) -> f64 {
+		if self.batteries.is_empty() {
+			return 0.0;
+		}
+		let energy: Option<f64> = self.batteries.iter().map(|battery| battery.energy_now).sum();
+		let full: Option<f64> = self.batteries.iter().map(|battery| battery.energy_full).sum();
+		match (energy, full) {
+			(Some(energy), Some(full)) if full > 0.0 => (energy / full).min(1.0),
+			_ => self.batteries.iter().map(|battery| battery.capacity).sum::<f64>() / self.batteries.len() as f64
+		}
+	}
+}
+
+impl Battery {
+	fn read(dir: &Path) -> Option<Battery> {
+		//"present" is missing on some laptops, but 0 for an empty battery slot
+		if read_trimmed(dir, "present").map(|v| v == "0").unwrap_or(false) {
+			return None;
+		}
+		let voltage = read_micro(dir, "voltage_now");
+		//batteries either report energy (uWh, uW) or charge (uAh, uA), which needs the voltage
+		let energy = |energy_file: &str, charge_file: &str| {
+			read_micro(dir, energy_file).or_else(|| match (read_micro(dir, charge_file), voltage) {
+				(Some(charge), Some(voltage)) => Some(charge * voltage),
+				_ => None
+			})
+		};
+		let energy_now = energy("energy_now", "charge_now");
+		let energy_full = energy("energy_full", "charge_full");
+		let power_now = energy("power_now", "current_now").map(|v| v.abs());
+		let capacity = match read_trimmed(dir, "capacity").and_then(|v| v.parse::<f64>().ok()) {
+			Some(v) => v / 100.0,
+			None => match (energy_now, energy_full) {
+				(Some(now), Some(full)) if full > 0.0 => now / full,
+				_ => return None
+			}
+		};
+		Some(Battery {
+			name: dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or(String::new()),
+			capacity: capacity.max(0.0).min(1.0),
+			status: read_trimmed(dir, "status").unwrap_or(String::from("Unknown")),
+			energy_now: energy_now,
+			energy_full: energy_full,
+			power_now: power_now
+		})
+	}
+}
+
+fn read_trimmed(dir: &Path, name: &str) -> Option<String> {
+	read_file(dir.join(name)).ok().map(|v| v.trim().to_owned())
+}
+
+//sysfs reports micro units, returns the base unit
+fn read_micro(dir: &Path, name: &str) -> Option<f64> {
+	read_trimmed(dir, name).and_then(|v| v.parse::<f64>().ok()).map(|v| v / 1000000.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::env;
+	use std::fs::File;
+	use std::io::Write;
+
+	//a sysfs-like tree of power supplies, each given as (name, [(file, content)])
+	fn fixture(test: &str, supplies: &[(&str, &[(&str, &str)])]) -> PowerInfo {
+		let root = env::temp_dir().join(format!("run-info-power-{}-{}", test, ::std::process::id()));
+		let _ = fs::remove_dir_all(&root);
+		for &(name, files) in supplies {
+			let dir = root.join(name);
+			fs::create_dir_all(&dir).unwrap();
+			for &(file, content) in files {
+				File::create(dir.join(file)).unwrap().write_all(content.as_bytes()).unwrap();
+			}
+		}
+		let mut power = PowerInfo::new(&root);
+		power.update().unwrap();
+		let _ = fs::remove_dir_all(&root);
+		power
+	}
+
+	fn assert_close(value: Option<f64>, expected: f64) {
+		match value {
+			Some(v) => assert!((v - expected).abs() < 1e-6, "{} != {}", v, expected),
+			None => panic!("no value, expected {}", expected)
+		}
+	}
+
+	#[test]
+	fn energy_battery() {
+		let power = fixture("energy", &[("BAT0", &[("type", "Battery\n"), ("status", "Discharging\n"),
+			("capacity", "50\n"), ("energy_now", "30000000\n"), ("energy_full", "60000000\n"),
+			("power_now", "15000000\n")])]);
+		assert_eq!(power.batteries.len(), 1);
+		assert_eq!(power.batteries[0].name, "BAT0");
+		assert_close(power.batteries[0].energy_now, 30.0);
+		assert_close(Some(power.capacity()), 0.5);
+		assert_close(power.draw(), 15.0);
+		assert!(!power.charging());
+	}
+
+	#[test]
+	fn charge_battery_uses_the_voltage() {
+		let power = fixture("charge", &[("BAT1", &[("type", "Battery\n"), ("status", "Discharging\n"),
+			("charge_now", "2000000\n"), ("charge_full", "4000000\n"), ("voltage_now", "12000000\n"),
+			("current_now", "-1000000\n")])]);
+		assert_eq!(power.batteries.len(), 1);
+		assert_close(power.batteries[0].energy_now, 24.0);
+		assert_close(power.batteries[0].energy_full, 48.0);
+		//without a capacity file the charge is calculated from the energy
+		assert_close(Some(power.batteries[0].capacity), 0.5);
+		assert_close(power.draw(), 12.0);
+	}
+
+	#[test]
+	fn empty_slot_is_skipped() {
+		let power = fixture("present", &[("BAT0", &[("type", "Battery\n"), ("present", "0\n"),
+			("capacity", "0\n")])]);
+		assert!(power.batteries.is_empty());
+		assert_eq!(power.draw(), None);
+	}
+
+	#[test]
+	fn mains_online_and_offline() {
+		let power = fixture("online", &[("AC", &[("type", "Mains\n"), ("online", "1\n")])]);
+		assert_eq!(power.ac_online, Some(true));
+		let power = fixture("offline", &[("AC", &[("type", "Mains\n"), ("online", "0\n")])]);
+		assert_eq!(power.ac_online, Some(false));
+		let power = fixture("no-mains", &[("BAT0", &[("type", "Battery\n"), ("capacity", "80\n")])]);
+		assert_eq!(power.ac_online, None);
+	}
+
+	#[test]
+	fn time_remaining_while_discharging() {
+		let power = fixture("discharging", &[("BAT0", &[("type", "Battery\n"), ("status", "Discharging\n"),
+			("energy_now", "30000000\n"), ("energy_full", "60000000\n"), ("power_now", "15000000\n")])]);
+		//30 Wh left at 15 W
+		assert_close(power.time_remaining(), 7200.0);
+	}
+
+	#[test]
+	fn time_remaining_while_charging() {
+		let power = fixture("charging", &[("BAT0", &[("type", "Battery\n"), ("status", "Charging\n"),
+			("energy_now", "45000000\n"), ("energy_full", "60000000\n"), ("power_now", "15000000\n")]),
+			("AC", &[("type", "Mains\n"), ("online", "1\n")])]);
+		assert!(power.charging());
+		assert_close(power.draw(), -15.0);
+		//15 Wh missing at 15 W
+		assert_close(power.time_remaining(), 3600.0);
+	}
+}
@@ -0,0 +1,161 @@
+/*
+Reads the Pressure Stall Information from /proc/pressure
+Shows the share of time tasks were stalled waiting for CPU, memory or IO
+*/
+
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+use fsutils::read_file;
+
+pub const PRESSURE_ROOT: &'static str = "/proc/pressure";
+
+pub struct PressureLine {
+	//averages over 10, 60 and 300 seconds, ranging from 0.0 to 1.0
+	pub avg10: f64,
+	pub avg60: f64,
+	pub avg300: f64,
+	pub total: u64 //stall time since boot (in us)
+}
+
+pub struct Pressure {
+	pub some: PressureLine, //at least one task was stalled
+	pub full: Option<PressureLine> //all non-idle tasks were stalled, missing for cpu on older kernels
+}
+
+pub struct PressureInfo {
+	root: PathBuf,
+	pub cpu: Pressure,
+	pub memory: Pressure,
+	pub io: Pressure
+}
+
+impl PressureInfo {
+	//root is usually PRESSURE_ROOT, but can point to any directory with the same files
+	pub fn new<P: AsRef<Path>>(root: P) -> PressureInfo {
+		PressureInfo {
+			root: root.as_ref().to_path_buf(),
+			cpu: Pressure::new(),
+			memory: Pressure::new(),
+			io: Pressure::new()
+		}
+	}
+
+	pub fn update(&mut self) -> Result<()> {
+		self.cpu = try!(Pressure::read(self.root.join("cpu")));
+		self.memory = try!(Pressure::read(self.root.join("memory")));
+		self.io = try!(Pressure::read(self.root.join("io")));
+		Ok(())
+	}
+}
+
+impl Pressure {
+	fn new() -> Pressure {
+		Pressure {
+			some: PressureLine::new(),
+			full: None
+		}
+	}
+
+	//parses lines like "some avg10=0.12 avg60=0.05 avg300=0.01 total=123456"
+	fn read<P: AsRef<Path>>(location: P) -> Result<Pressure> {
+		let plain = try!(read_file(location));
+		let mut pressure = Pressure::new();
+		for line in plain.lines() {
+			let mut columns = line.split_whitespace();
+			let kind = columns.next().unwrap_or("");
+			let mut values = PressureLine::new();
+			for column in columns {
+				let mut pair = column.splitn(2, '=');
+				let key = pair.next().unwrap_or("");
+				let value = pair.next().unwrap_or("");
+				match key {
+					"avg10" => values.avg10 = parse_percentage(value),
+					"avg60" => values.avg60 = parse_percentage(value),
+					"avg300" => values.avg300 = parse_percentage(value),
+					"total" => values.total = value.parse::<u64>().expect("incorrect pressure information format"),
+					_ => {}
+				}
+			}
+			match kind {
+				"some" => pressure.some = values,
+				"full" => pressure.full = Some(values),
+				_ => {}
+			}
+		}
+		Ok(pressure)
+	}
+}
+
+impl PressureLine {
+	fn new() -> PressureLine {
+		PressureLine {
+			avg10: 0.0,
+			avg60: 0.0,
+			avg300: 0.0,
+			total: 0
+		}
+	}
+}
+
+fn parse_percentage(value: &str) -> f64 {
+	let percentage = value.parse::<f64>().expect("incorrect pressure information format");
+	(percentage / 100.0).max(0.0).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::env;
+	use std::fs::{self, File};
+	use std::io::Write;
+
+	//a /proc/pressure like directory with the given (file, content) pairs
+	fn fixture(test: &str, files: &[(&str, &str)]) -> PathBuf {
+		let root = env::temp_dir().join(format!("run-info-pressure-{}-{}", test, ::std::process::id()));
+		let _ = fs::remove_dir_all(&root);
+		fs::create_dir_all(&root).unwrap();
+		for &(file, content) in files {
+			File::create(root.join(file)).unwrap().write_all(content.as_bytes()).unwrap();
+		}
+		root
+	}
+
+	const FULL: &'static str = "some avg10=1.50 avg60=0.75 avg300=0.25 total=123456\n\
+	                            full avg10=0.50 avg60=0.00 avg300=0.10 total=4567\n";
+
+	#[test]
+	fn some_and_full_lines() {
+		//cpu has no "full" line on kernels before 5.13
+		let root = fixture("lines", &[("cpu", "some avg10=12.34 avg60=5.00 avg300=0.01 total=987654321\n"),
+			("memory", FULL), ("io", FULL)]);
+		let mut pressure = PressureInfo::new(&root);
+		pressure.update().unwrap();
+		let _ = fs::remove_dir_all(&root);
+		assert_eq!(pressure.cpu.some.avg10, 0.1234);
+		assert_eq!(pressure.cpu.some.avg60, 0.05);
+		assert_eq!(pressure.cpu.some.total, 987654321);
+		assert!(pressure.cpu.full.is_none());
+		assert_eq!(pressure.memory.some.avg10, 0.015);
+		assert_eq!(pressure.memory.some.avg300, 0.0025);
+		let full = pressure.io.full.as_ref().expect("no full line");
+		assert_eq!((full.avg10, full.avg60, full.avg300, full.total), (0.005, 0.0, 0.001, 4567));
+	}
+
+	#[test]
+	fn missing_files_on_older_kernels() {
+		let root = fixture("missing", &[("cpu", FULL)]);
+		assert!(PressureInfo::new(&root).update().is_err());
+		let _ = fs::remove_dir_all(&root);
+		assert!(PressureInfo::new(&root).update().is_err());
+	}
+
+	#[test]
+	fn percentages_are_clamped() {
+		assert_eq!(parse_percentage("100.00"), 1.0);
+		assert_eq!(parse_percentage("250.5"), 1.0);
+		assert_eq!(parse_percentage("-3.0"), 0.0);
+		assert_eq!(parse_percentage("0.00"), 0.0);
+	}
+}
@@ -11,7 +11,16 @@ use term::{
 
 use meminfo::MemInfo;
 use cpuinfo::CPUInfo;
-use graph::Graph;
+use cgroup::CGroupInfo;
+use pressure::{Pressure, PressureInfo, PressureLine};
+use numa::{self, NumaInfo};
+use vmstat::VmStat;
+use power::PowerInfo;
+use process::ProcessInfo;
+use proctable::{Group, Row, ProcessView};
+use history::{History, TieredGraph};
+use session::Session;
+use widgets::{Layout, Constraint, Text, Header, Bar, Graph, Table, draw, split};
 
 use std::io::Stdout;
 
@@ -30,150 +39,784 @@ macro_rules! pl {
     }
 }
 
-//normal mode
-pub fn print(mut term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
-             cpu: &CPUInfo, mem: &MemInfo, graph: &mut Graph) {
-	let mut lines_printed = 19;
+//the optional sections, None if they are not available or not enabled
+pub struct Sections<'a> {
+	pub cgroup: Option<&'a CGroupInfo>,
+	pub pressure: Option<&'a PressureInfo>,
+	pub numa: Option<&'a NumaInfo>,
+	pub vmstat: Option<&'a VmStat>,
+	pub power: Option<&'a PowerInfo>,
+	pub users: Option<&'a [Group]>,
+	pub services: Option<&'a [Group]>,
+	pub matching: Option<&'a [Group]> //the processes matching --filter
+}
 
-	//CPU
+//the column of the percentages next to the bars
+const VALUE_WIDTH: usize = 9;
 
-	//"x processes on x cores"
-	print_header(term, &settings, 57, String::from("CPU"));
-	print_highlighted(term, &settings, format!("{}", cpu.processes));
-	if cpu.processes > 1 {
-		p!(term, " active processes on ");
-	} else {
-	   	p!(term, " active process on ");
+//number of users/services shown, the busiest ones
+const GROUP_ROWS: usize = 5;
+
+//number of threads shown per process in --pid mode, the busiest ones
+const THREAD_ROWS: usize = 10;
+
+//the headers span the label, the bar and the value next to it
+fn header_width(settings: &Settings) -> usize {
+	settings.bar_width + 17
+}
+
+//what a layout of the config file is made of
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Section {
+	Cpu,
+	Graph,
+	Memory,
+	VmStat,
+	Numa,
+	Power,
+	Filter,
+	Users,
+	Services,
+	Container,
+	Pressure,
+	Compact //the bars of the small mode
+}
+
+const SECTION_NAMES: [(&'static str, Section); 12] = [
+	("cpu", Section::Cpu), ("graph", Section::Graph), ("memory", Section::Memory), ("vmstat", Section::VmStat),
+	("numa", Section::Numa), ("power", Section::Power), ("filter", Section::Filter), ("users", Section::Users),
+	("services", Section::Services), ("container", Section::Container), ("pressure", Section::Pressure),
+	("compact", Section::Compact)
+];
+
+//the normal mode without a layout, one section below the other
+const DEFAULT_LAYOUT: [Section; 11] = [
+	Section::Cpu, Section::Graph, Section::Memory, Section::VmStat, Section::Numa, Section::Power, Section::Filter,
+	Section::Users, Section::Services, Section::Container, Section::Pressure
+];
+
+//space between the sections of a row
+const COLUMN_GAP: usize = 2;
+
+//a row of the layout: the sections side by side, each may have a width in characters or percent ( -> "cpu:60% memory" )
+//the sections without a width share the rest
+pub fn parse_layout_row(row: &str) -> Result<Vec<(Section, Constraint)>, String> {
+	let mut result = Vec::new();
+	for entry in row.split_whitespace() {
+		let (name, width) = match entry.find(':') {
+			Some(index) => (&entry[..index], Some(&entry[index + 1..])),
+			None => (entry, None)
+		};
+		let section = match SECTION_NAMES.iter().find(|section| section.0 == name) {
+			Some(v) => v.1,
+			None => {
+				let names: Vec<&str> = SECTION_NAMES.iter().map(|section| section.0).collect();
+				return Err(format!("unknown section {} in the layout, expected one of {}.", name, names.join(", ")));
+			}
+		};
+		let constraint = match width {
+			None => Constraint::Fill,
+			Some(width) => {
+				let (number, percent) = if width.ends_with('%') { (&width[..width.len() - 1], true) } else { (width, false) };
+				match number.parse::<usize>() {
+					Ok(v) if percent && v > 0 && v <= 100 => Constraint::Percentage(v),
+					Ok(v) if !percent && v > 0 => Constraint::Length(v),
+					_ => return Err(format!("width of {} must be a number of characters or a percentage.", name))
+				}
+			}
+		};
+		result.push((section, constraint));
 	}
-	print_highlighted(term, &settings, format!("{}", cpu.cores));
-	pl!(term, " cores      ");
-	pl!(term, "");
+	if result.is_empty() {
+		return Err(String::from("a row of the layout is empty."));
+	}
+	Ok(result)
+}
 
-	//print bars
-	print_highlighted(term, &settings, String::from("TOTAL: "));
-	let total_percentage = calc_cpu_load_percentage(&cpu.total_load);
-	graph.push(total_percentage); //push new data
-	print_progress_bar(term, &settings, total_percentage, 40, color::RED);
-	print_highlighted(term, &settings, format!(" {} %   ", format_float(total_percentage)));
-	pl!(term, "");
+//normal mode, in the layout of the config file
+pub fn print(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
+             cpu: &CPUInfo, mem: &MemInfo, sections: &Sections, history: &History) {
+	let rows = layout_rows(settings).iter()
+		.map(|row| Layout::Columns(row_columns(row, settings, cpu, mem, sections, history)))
+		.collect();
+	draw(term, settings, &Layout::Rows(rows));
+}
+
+//the section whose header was clicked, at a line and column of the normal mode
+pub fn header_at(settings: &Settings, cpu: &CPUInfo, mem: &MemInfo, sections: &Sections, history: &History,
+                 line: usize, column: usize) -> Option<Section> {
+	let (width, _) = terminal_size();
+	let mut top = 0;
+	for row in layout_rows(settings) {
+		let columns = row_columns(&row, settings, cpu, mem, sections, history);
+		let constraints: Vec<Constraint> = columns.iter().map(|column| column.0).collect();
+		let mut left = 0;
+		let mut height = 0;
+		for (index, (width, column_layout)) in split(width, &constraints).into_iter().zip(columns.iter()).enumerate() {
+			let lines = column_layout.1.render(width).len();
+			//the sections are at the even columns, between them are the gaps
+			let name = row[index / 2].0;
+			if line == top && lines > 0 && index % 2 == 0 && column >= left && column < left + width && title(name).is_some() {
+				return Some(name);
+			}
+			left += width;
+			height = height.max(lines);
+		}
+		top += height;
+	}
+	None
+}
+
+//the rows of the config file, or one section in each row
+fn layout_rows(settings: &Settings) -> Vec<Vec<(Section, Constraint)>> {
+	if settings.layout.is_empty() {
+		DEFAULT_LAYOUT.iter().map(|name| vec![(*name, Constraint::Fill)]).collect()
+	}
+	else {
+		settings.layout.clone()
+	}
+}
+
+fn row_columns<'a>(row: &[(Section, Constraint)], settings: &Settings, cpu: &CPUInfo, mem: &MemInfo,
+                   sections: &Sections, history: &'a History) -> Vec<(Constraint, Layout<'a>)> {
+	let mut columns = Vec::new();
+	for &(name, width) in row {
+		if !columns.is_empty() {
+			columns.push((Constraint::Length(COLUMN_GAP), Layout::Rows(Vec::new())));
+		}
+		columns.push((width, section(name, settings, cpu, mem, sections, history)));
+	}
+	columns
+}
+
+//the header a section starts with
+fn title(name: Section) -> Option<&'static str> {
+	match name {
+		Section::Cpu => Some("CPU"),
+		Section::Memory => Some("MEMORY"),
+		Section::Numa => Some("NUMA"),
+		Section::Power => Some("POWER"),
+		Section::Filter => Some("FILTER"),
+		Section::Users => Some("USERS"),
+		Section::Services => Some("SERVICES"),
+		Section::Container => Some("CONTAINER"),
+		Section::Pressure => Some("PRESSURE"),
+		Section::Graph | Section::VmStat | Section::Compact => None
+	}
+}
+
+//empty if the section is not available (or the graph is hidden), only the header if it is collapsed
+fn section<'a>(name: Section, settings: &Settings, cpu: &CPUInfo, mem: &MemInfo, sections: &Sections,
+               history: &'a History) -> Layout<'a> {
+	let section = match name {
+		Section::Cpu => Some(cpu_section(settings, cpu, history)),
+		Section::Graph if settings.enable_graph => Some(Graph::new(settings, &history.total, settings.zoom).into()),
+		Section::Graph => None,
+		Section::Memory => Some(memory_section(settings, mem, history)),
+		Section::VmStat => sections.vmstat.map(vmstat_section), //is the box thrashing right now?
+		Section::Numa => sections.numa.map(|numa| numa_section(settings, cpu, numa)),
+		Section::Power => sections.power.map(|power| power_section(settings, power)),
+		Section::Filter => sections.matching.map(|matching| group_section(settings, "FILTER", cpu, mem, matching)),
+		Section::Users => sections.users.map(|users| group_section(settings, "USERS", cpu, mem, users)),
+		Section::Services => sections.services.map(|services| group_section(settings, "SERVICES", cpu, mem, services)),
+		Section::Container => sections.cgroup.map(|cgroup| container_section(settings, cpu, mem, cgroup)),
+		Section::Pressure => sections.pressure.map(|pressure| pressure_section(settings, pressure, history)),
+		Section::Compact => Some(compact_section(settings, cpu, mem, sections, history))
+	};
+	match (section, title(name)) {
+		(Some(_), Some(title)) if settings.collapsed.contains(&name) => header(settings, title),
+		(section, _) => section.unwrap_or(Layout::Rows(Vec::new()))
+	}
+}
 
-	let mut core_counter = 1;
-	for core_load in &cpu.cores_load {
-		p!(term, "CPU {}: ", core_counter);
+//the headers span the label, the bar and the value next to it
+fn header<'a>(settings: &Settings, title: &str) -> Layout<'a> {
+	Layout::Columns(vec![(Constraint::Length(header_width(settings)), Header::new(title).into())])
+}
+
+fn blank<'a>() -> Layout<'a> {
+	Text::new().into()
+}
+
+fn percent(value: f64) -> Text {
+	Text::new().plain(format!(" {} %", format_float(value)))
+}
+
+//a label, the bar of a load, its value and the averages ( -> CPU 1: [===        ] 12.5 %   avg 1m / 5m: .. )
+fn load_row<'a>(settings: &Settings, label: Text, value: f64, color: u32, text: Text, history: &TieredGraph) -> Layout<'a> {
+	Layout::Columns(vec![
+		(Constraint::Length(label.width()), label.into()),
+		(Constraint::Length(settings.bar_width + 2), Bar::load(settings, value, color).into()),
+		(Constraint::Length(VALUE_WIDTH), text.into()),
+		(Constraint::Fill, averages(history).into())
+	])
+}
+
+//a label, a bar with `size` segments and what follows it ( -> BAT0: [======    ] 60 %  Discharging )
+fn bar_row<'a>(label: Text, bar: Bar, size: usize, text: Text) -> Layout<'a> {
+	Layout::Columns(vec![
+		(Constraint::Length(label.width()), label.into()),
+		(Constraint::Length(size + 2), bar.into()),
+		(Constraint::Fill, text.into())
+	])
+}
+
+//the total load and the load of each core
+fn cpu_rows<'a>(settings: &Settings, cpu: &CPUInfo, history: &History) -> Vec<Layout<'a>> {
+	let total_percentage = calc_cpu_load_percentage(&cpu.total_load);
+	let mut rows = vec![load_row(settings, Text::new().highlight("TOTAL: "), total_percentage, color::RED,
+		Text::new().highlight(format!(" {} %", format_float(total_percentage))), &history.total)];
+	for (index, core_load) in cpu.cores_load.iter().enumerate() {
 		let core_percentage = calc_cpu_load_percentage(&core_load);
-		print_progress_bar(term, &settings, core_percentage, 40, color::GREEN);
-		p!(term, " {} %   ", format_float(core_percentage));
-		pl!(term, "");
-		lines_printed += 1;
-		core_counter += 1;
+		let label = Text::new().plain(format!("CPU {}: ", index + 1));
+		rows.push(match history.cores.get(index) {
+			Some(core_history) => load_row(settings, label, core_percentage, color::GREEN, percent(core_percentage), core_history),
+			None => bar_row(label, Bar::load(settings, core_percentage, color::GREEN), settings.bar_width, percent(core_percentage))
+		});
 	}
-	pl!(term, "");
+	rows
+}
+
+fn cpu_section<'a>(settings: &Settings, cpu: &CPUInfo, history: &History) -> Layout<'a> {
+	let mut rows = vec![
+		header(settings, "CPU"),
+		//"x processes on x cores"
+		Text::new()
+			.highlight(format!("{}", cpu.processes))
+			.plain(if cpu.processes > 1 { " active processes on " } else { " active process on " })
+			.highlight(format!("{}", cpu.cores))
+			.plain(" cores")
+			.into(),
+		blank()
+	];
+	rows.extend(cpu_rows(settings, cpu, history));
+	rows.push(blank());
+	Layout::Rows(rows)
+}
+
+fn memory_section<'a>(settings: &Settings, mem: &MemInfo, history: &History) -> Layout<'a> {
+	let memory_use = mem.memory_use();
+	let swap_use = mem.swap_use();
+	Layout::Rows(vec![
+		header(settings, "MEMORY"),
+		blank(),
+		load_row(settings, Text::new().plain("  RAM: "), memory_use, color::GREEN, Text::new(), &history.ram),
+		usage(format!("             {}", format_gib(mem.total - mem.free - mem.cached)), mem.total, memory_use),
+		blank(),
+		load_row(settings, Text::new().plain(" SWAP: "), swap_use, color::GREEN, Text::new(), &history.swap),
+		usage(format!("               {}", format_gib(mem.swap_used)), mem.swap_total, swap_use),
+		blank()
+	])
+}
 
-	//print graph
+//( -> 1.2 GiB / 7.7 GiB ( 15.5% ) )
+fn usage<'a>(used: String, total: u64, share: f64) -> Layout<'a> {
+	Text::new()
+		.highlight(used)
+		.plain(" GiB / ")
+		.highlight(format_gib(total))
+		.plain(" GiB (")
+		.highlight(format!(" {}% ", format_float(share)))
+		.plain(")")
+		.into()
+}
+
+fn vmstat_section<'a>(vmstat: &VmStat) -> Layout<'a> {
+	Layout::Rows(vec![
+		Text::new()
+			.plain("   VM: faults ")
+			.highlight(format_rate(vmstat.minor_faults))
+			.plain(" minor, ")
+			.highlight(format_rate(vmstat.major_faults))
+			.plain(" major   swapped pages in ")
+			.highlight(format_rate(vmstat.swap_in))
+			.plain(" out ")
+			.highlight(format_rate(vmstat.swap_out))
+			.into(),
+		Text::new()
+			.plain("       reclaim scans ")
+			.highlight(format_rate(vmstat.scanned))
+			.plain("   oom kills ")
+			.highlight(format_rate(vmstat.oom_kills))
+			.into(),
+		blank()
+	])
+}
+
+//one sub-section per node with the summed up load of its cores
+fn numa_section<'a>(settings: &Settings, cpu: &CPUInfo, numa: &NumaInfo) -> Layout<'a> {
+	let mut rows = vec![header(settings, "NUMA"), blank()];
+	for node in &numa.nodes {
+		let node_percentage = calc_cpu_load_percentage(&NumaInfo::node_load(node, cpu));
+		rows.push(Text::new()
+			.highlight(format!("NODE {}", node.id))
+			.plain(format!(" (cpus {})", numa::format_cpulist(&node.cpus)))
+			.into());
+		rows.push(bar_row(Text::new().plain("  CPU: "), Bar::load(settings, node_percentage, color::RED),
+			settings.bar_width, percent(node_percentage)));
+		rows.push(bar_row(Text::new().plain("  RAM: "), Bar::load(settings, node.memory_use(), color::GREEN),
+			settings.bar_width, Text::new().plain(format!(" {} / {} GiB", format_gib(node.mem_used), format_gib(node.mem_total)))));
+		rows.push(Text::new()
+			.plain("       pages hit: ")
+			.highlight(format!("{}", node.numa_hit))
+			.plain("  miss: ")
+			.highlight(format!("{}", node.numa_miss))
+			.plain("  foreign: ")
+			.highlight(format!("{}", node.numa_foreign))
+			.into());
+		rows.push(blank());
+	}
+	Layout::Rows(rows)
+}
+
+//charge, draw and time remaining of the batteries
+fn power_section<'a>(settings: &Settings, power: &PowerInfo) -> Layout<'a> {
+	if power.batteries.is_empty() {
+		return Layout::Rows(Vec::new());
+	}
+	let mut rows = vec![header(settings, "POWER"), blank()];
+	for battery in &power.batteries {
+		rows.push(bar_row(Text::new().plain(pad_left(format!("{}: ", battery.name), 7)),
+			Bar::new(battery.capacity, color::GREEN), settings.bar_width,
+			Text::new().plain(format!(" {} %  ", format_float(battery.capacity))).highlight(battery.status.clone())));
+	}
+	let mut line = match power.draw() {
+		Some(watts) if watts < 0.0 => Text::new().plain("       charge: ").highlight(format!("{:.1} W", -watts)),
+		Some(watts) => Text::new().plain("       draw: ").highlight(format!("{:.1} W", watts)),
+		None => Text::new().plain("       draw: -")
+	};
+	if let Some(secs) = power.time_remaining() {
+		line = line
+			.plain("   ")
+			.highlight(format_duration(secs as i64))
+			.plain(if power.charging() { " until full" } else { " remaining" });
+	}
+	line = match power.ac_online {
+		Some(true) => line.plain("   AC: online"),
+		Some(false) => line.plain("   AC: offline"),
+		None => line
+	};
+	rows.push(line.into());
+	rows.push(blank());
+	Layout::Rows(rows)
+}
+
+//the busiest users or services with their share of the machine
+fn group_section<'a>(settings: &Settings, title: &str, cpu: &CPUInfo, mem: &MemInfo, groups: &[Group]) -> Layout<'a> {
+	let mut rows = vec![
+		header(settings, title),
+		blank(),
+		Text::new().highlight("                CPU                         RAM").into()
+	];
+	for group in groups.iter().take(GROUP_ROWS) {
+		let cpu_share = (group.cpu_usage / cpu.cores.max(1) as f64).min(1.0);
+		let ram_share = if mem.total > 0 { (group.rss as f64 / mem.total as f64).min(1.0) } else { 0.0 };
+		rows.push(Layout::Columns(vec![
+			(Constraint::Length(15), Text::new().plain(truncate_chars(group.name.clone(), 14)).into()),
+			(Constraint::Length(17), Bar::load(settings, cpu_share, color::RED).into()),
+			(Constraint::Length(VALUE_WIDTH), percent(cpu_share).into()),
+			(Constraint::Length(17), Bar::load(settings, ram_share, color::GREEN).into()),
+			(Constraint::Fill, Text::new()
+				.plain(format!(" {} GiB  ", format_gib(group.rss)))
+				.highlight(format!("{}", group.processes))
+				.plain(if group.processes > 1 { " processes" } else { " process" })
+				.into())
+		]));
+	}
+	rows.push(blank());
+	Layout::Rows(rows)
+}
+
+//usage relative to the limits of the container
+fn container_section<'a>(settings: &Settings, cpu: &CPUInfo, mem: &MemInfo, cgroup: &CGroupInfo) -> Layout<'a> {
+	let limit = match cgroup.cpu_limit {
+		Some(limit) => format!("{:.1}", limit),
+		None => format!("{}", cpu.cores)
+	};
+	let memory_use = cgroup.memory_use(mem.total);
+	Layout::Rows(vec![
+		header(settings, "CONTAINER"),
+		blank(),
+		bar_row(Text::new().plain("  CPU: "), Bar::load(settings, cgroup.cpu_usage, color::RED), settings.bar_width,
+			Text::new().plain(format!(" {} % of ", format_float(cgroup.cpu_usage))).highlight(limit).plain(" cores")),
+		Text::new()
+			.plain("       throttled in ")
+			.highlight(format!("{}", cgroup.throttled_periods))
+			.plain(format!(" of {} periods (", cgroup.periods))
+			.highlight(format!(" {:.2} s ", cgroup.throttled_usec as f64 / 1000000.0))
+			.plain(")")
+			.into(),
+		bar_row(Text::new().plain("  RAM: "), Bar::load(settings, memory_use, color::GREEN), settings.bar_width,
+			percent(memory_use)),
+		Text::new()
+			.highlight(format!("       {}", format_gib(cgroup.memory_current)))
+			.plain(" GiB / ")
+			.highlight(format_limit(cgroup.memory_max))
+			.into(),
+		Text::new()
+			.plain(" SWAP: ")
+			.highlight(format_gib(cgroup.swap_current))
+			.plain(" GiB / ")
+			.highlight(format_limit(cgroup.swap_max))
+			.into(),
+		Text::new()
+			.plain("  OOM: ")
+			.highlight(format!("{}", cgroup.events_oom))
+			.plain(" events, ")
+			.highlight(format!("{}", cgroup.events_oom_kill))
+			.plain(" kills (limit hit ")
+			.highlight(format!("{}", cgroup.events_max))
+			.plain(" times)")
+			.into(),
+		blank()
+	])
+}
+
+//some/full stall bars (avg10) followed by avg10/avg60/avg300
+fn pressure_section<'a>(settings: &Settings, pressure: &PressureInfo, history: &History) -> Layout<'a> {
+	Layout::Rows(vec![
+		header(settings, "PRESSURE"),
+		blank(),
+		Text::new().plain(pad_string(String::new(), 45)).highlight("avg10 / 60 / 300 %").into(),
+		pressure_rows(settings, "  CPU", &pressure.cpu, &history.pressure_cpu),
+		pressure_rows(settings, "  MEM", &pressure.memory, &history.pressure_memory),
+		pressure_rows(settings, "   IO", &pressure.io, &history.pressure_io),
+		blank()
+	])
+}
+
+fn pressure_rows<'a>(settings: &Settings, name: &str, pressure: &Pressure, history: &TieredGraph) -> Layout<'a> {
+	let stall_values = |line: &PressureLine| Text::new().plain(format!(" {} / {} / {}", format_float(line.avg10),
+		format_float(line.avg60), format_float(line.avg300)));
+	Layout::Rows(vec![
+		Layout::Columns(vec![
+			(Constraint::Length(12), Text::new().plain(format!("{} some: ", name)).into()),
+			(Constraint::Length(32), Bar::load(settings, pressure.some.avg10, color::YELLOW).into()),
+			(Constraint::Length(21), stall_values(&pressure.some).into()),
+			(Constraint::Fill, averages(history).into())
+		]),
+		match pressure.full {
+			Some(ref full) => bar_row(Text::new().plain("      full: "), Bar::load(settings, full.avg10, color::RED), 30,
+				stall_values(full)),
+			None => Text::new().plain("      full: -").into()
+		}
+	])
+}
+
+fn format_limit(limit: Option<u64>) -> String {
+	match limit {
+		Some(bytes) => format!("{} GiB", format_gib(bytes)),
+		None => String::from("unlimited")
+	}
+}
+
+pub fn print_small_mode(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
+                        cpu: &CPUInfo, mem: &MemInfo, sections: &Sections, history: &History) {
+	draw(term, settings, &compact_section(settings, cpu, mem, sections, history));
+}
+
+//the bars of the CPU, memory, battery and container below each other
+fn compact_section<'a>(settings: &Settings, cpu: &CPUInfo, mem: &MemInfo, sections: &Sections,
+                       history: &History) -> Layout<'a> {
+	let memory_use = mem.memory_use();
+	let swap_use = mem.swap_use();
+	let mut rows = cpu_rows(settings, cpu, history);
+	rows.push(blank());
+	rows.push(load_row(settings, Text::new().highlight("RAM:   "), memory_use, color::YELLOW, percent(memory_use), &history.ram));
+	if swap_use > 0.0 {
+		rows.push(load_row(settings, Text::new().highlight("SWAP:  "), swap_use, color::RED, percent(swap_use), &history.swap));
+	}
+	if let Some(power) = sections.power {
+		if !power.batteries.is_empty() {
+			let mut text = Text::new().plain(pad_string(format!(" {} %", format_float(power.capacity())), VALUE_WIDTH));
+			if let Some(watts) = power.draw() {
+				text = text.plain(format!("{:.1} W", watts));
+			}
+			rows.push(bar_row(Text::new().highlight("BAT:   "), Bar::new(power.capacity(), color::GREEN), settings.bar_width, text));
+		}
+	}
+	if let Some(cgroup) = sections.cgroup {
+		let memory_use = cgroup.memory_use(mem.total);
+		rows.push(bar_row(Text::new().highlight("C-CPU: "), Bar::load(settings, cgroup.cpu_usage, color::RED),
+			settings.bar_width, percent(cgroup.cpu_usage)));
+		rows.push(bar_row(Text::new().highlight("C-RAM: "), Bar::load(settings, memory_use, color::YELLOW),
+			settings.bar_width, percent(memory_use)));
+	}
+	rows.push(blank());
+	Layout::Rows(rows)
+}
+
+//follows the processes given with --pid
+pub fn print_process_mode(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
+                          cpu: &CPUInfo, mem: &MemInfo, processes: &[ProcessInfo]) {
+	let rows = processes.iter().map(|process| process_section(settings, cpu, mem, process)).collect();
+	draw(term, settings, &Layout::Rows(rows));
+}
+
+fn process_section<'a>(settings: &Settings, cpu: &CPUInfo, mem: &MemInfo, process: &'a ProcessInfo) -> Layout<'a> {
+	let mut name = truncate_chars(process.name.clone(), 20);
+	if !process.alive {
+		name.push_str(", exited");
+	}
+	//the bar, averages and graph show the share of the whole machine
+	let cpu_share = (process.cpu_usage / cpu.cores.max(1) as f64).min(1.0);
+	let memory_use = if mem.total > 0 { (process.rss as f64 / mem.total as f64).min(1.0) } else { 0.0 };
+	let mut rows = vec![
+		header(settings, &format!("PROCESS {} ({})", process.pid, name)),
+		Text::new()
+			.plain("state: ")
+			.highlight(format!("{}", process.state))
+			.plain("   threads: ")
+			.highlight(format!("{}", process.threads))
+			.plain("   open files: ")
+			.highlight(format_optional(process.fds.map(|fds| format!("{}", fds))))
+			.plain("   of one core: ")
+			.highlight(format!("{} %", format_float(process.cpu_usage))) //like top
+			.into(),
+		load_row(settings, Text::new().plain("  CPU: "), cpu_share, color::RED, percent(cpu_share), &process.cpu_history),
+		bar_row(Text::new().plain("  RAM: "), Bar::load(settings, memory_use, color::GREEN), settings.bar_width, Text::new()
+			.plain(" RSS ")
+			.highlight(format!("{} GiB", format_gib(process.rss)))
+			.plain("   PSS ")
+			.highlight(format_optional(process.pss.map(|pss| format!("{} GiB", format_gib(pss)))))),
+		Text::new()
+			.plain("   IO: read ")
+			.highlight(format_optional(process.read_rate.map(format_bytes_rate)))
+			.plain("   write ")
+			.highlight(format_optional(process.write_rate.map(format_bytes_rate)))
+			.into(),
+		blank()
+	];
+	if settings.threads {
+		rows.extend(thread_rows(process));
+	}
 	if settings.enable_graph {
-    	print_graph(&mut term, &settings, graph);
+		rows.push(Graph::new(settings, &process.cpu_history, settings.zoom).into());
+	}
+	Layout::Rows(rows)
+}
+
+//the busiest threads of a process
+fn thread_rows<'a>(process: &ProcessInfo) -> Vec<Layout<'a>> {
+	//cpu usage is given in % of one core, like for the whole process
+	let header_row = vec![pad_left(String::from("TID"), 7), pad_left(String::from("CPU %"), 7), String::from("S"),
+		pad_left(String::from("CPU"), 4), String::from("NAME")];
+	let mut table = Table::new(header_row, vec![8, 8, 2, 6]);
+	let shown = process.thread_list.len().min(THREAD_ROWS);
+	for thread in &process.thread_list[..shown] {
+		table = table.row(vec![pad_left(format!("{}", thread.tid), 7), pad_left(format_float(thread.cpu_usage), 7),
+			format!("{}", thread.state), pad_left(format!("{}", thread.processor), 4), truncate_chars(thread.name.clone(), 20)]);
+	}
+	let mut rows = vec![table.into()];
+	if process.thread_list.len() > shown {
+		rows.push(Text::new().plain(format!("        ... and {} more", process.thread_list.len() - shown)).into());
 	}
 	else {
-	    lines_printed -= 6;
+		rows.push(blank());
 	}
+	rows
+}
 
-	//MEMORY
+//lines of the process table that are not process rows
+const PROCESS_TABLE_LINES: usize = 6;
 
-	print_header(term, &settings, 57, String::from("MEMORY"));
-	pl!(term, "");
+//number of process rows that fit into the terminal
+pub fn visible_process_rows() -> usize {
+	let (_, height) = terminal_size();
+	height.saturating_sub(PROCESS_TABLE_LINES).max(1)
+}
 
-	let memory_use: f64 = mem.memory_use();
-	let swap_use: f64 = mem.swap_use();
+//the lines above the first process: header, totals, a blank line and the column titles
+const PROCESS_TABLE_TOP: usize = 4;
 
-	p!(term, "  RAM: "); //RAM BAR
-	print_progress_bar(term, &settings, memory_use, 40, color::GREEN);
-	pl!(term, "");
-	print_highlighted(term, &settings, format!("             {}",
-         format_gib(mem.total - mem.free - mem.cached)));
-	p!(term, " GiB / ");
-	print_highlighted(term, &settings, format!("{}", format_gib(mem.total)));
-	p!(term, " GiB (");
-	print_highlighted(term, &settings, format!(" {}% ", format_float(memory_use)));
-	p!(term, ")");
-	pl!(term, "\n");
-
-	p!(term, " SWAP: "); //SWAP BAR
-	print_progress_bar(term, &settings, swap_use, 40, color::GREEN);
-	pl!(term, "");
-	print_highlighted(term, &settings, format!("               {}", format_gib(mem.swap_used)));
-	p!(term, " GiB / ");
-	print_highlighted(term, &settings, format!("{}", format_gib(mem.swap_total)));
-	p!(term, " GiB (");
-	print_highlighted(term, &settings, format!(" {}% ", format_float(swap_use)));
-	p!(term, ")");
-	pl!(term, "\n");
-
-	for _ in 0..lines_printed {
-		let _ = term.cursor_up();
-	}
-}
-
-pub fn print_small_mode(mut term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
-                        cpu: &CPUInfo, mem: &MemInfo) {
-    let mut lines_printed = 4;
-    //CPU
-	print_highlighted(term, &settings, format!("TOTAL: "));
-    let total_percentage = calc_cpu_load_percentage(&cpu.total_load);
-    print_progress_bar(term, &settings, total_percentage, 40, color::RED);
-    p!(term, " {} %   ", format_float(total_percentage));
-    pl!(term, "");
-    let mut core_counter = 1;
-    for core_load in &cpu.cores_load {
-        p!(term, "CPU {}: ", core_counter);
-        let core_percentage = calc_cpu_load_percentage(&core_load);
-        print_progress_bar(term, &settings, core_percentage, 40, color::GREEN);
-        p!(term, " {} %   ", format_float(core_percentage));
-        pl!(term, "");
-        lines_printed += 1;
-        core_counter += 1;
-    }
-    pl!(term, "");
-    //MEM
-	let memory_use: f64 = mem.memory_use();
-	let swap_use: f64 = mem.swap_use();
-    print_highlighted(term, &settings, format!("RAM:   "));
-    print_progress_bar(term, &settings, memory_use, 40, color::YELLOW);
-    p!(term, " {} %   ", format_float(memory_use));
-    pl!(term, "");
-    if swap_use > 0.0 {
-        print_highlighted(term, &settings, format!("SWAP:  "));
-        print_progress_bar(term, &settings, swap_use, 40, color::RED);
-        p!(term, " {}   ", format_float(swap_use));
-        pl!(term, "");
-        lines_printed += 1;
-    }
-    pl!(term, "");
-    
-    for _ in 0..lines_printed {
-        let _ = term.cursor_up();
-    }
+//the index of the process shown at a line of the screen
+pub fn process_row_at(line: usize, scroll: usize) -> Option<usize> {
+	if line >= PROCESS_TABLE_TOP && line < PROCESS_TABLE_TOP + visible_process_rows() {
+		Some(scroll + line - PROCESS_TABLE_TOP)
+	} else {
+		None
+	}
 }
 
+//what the process list (-P) or tree (-t) shows
+pub struct ProcessList<'a> {
+	pub rows: &'a [Row],
+	pub totals: Group,
+	pub filtered: bool, //the totals only sum up the matching processes
+	pub view: &'a mut ProcessView
+}
+
+//the process list or tree, as many rows as fit into the terminal
+pub fn print_process_table(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
+                           cpu: &CPUInfo, mem: &MemInfo, list: ProcessList) {
+	let (width, _) = terminal_size();
+	let visible = visible_process_rows();
+	let view = list.view;
+	view.scroll_to_selection(list.rows, visible);
+	let selected = view.selected_index(list.rows);
+	let tree = list.rows.iter().any(|row| row.depth > 0 || row.children > 0);
+
+	let totals = Text::new().highlight(format!("{}", list.totals.processes));
+	let totals = if list.filtered {
+		//only the matching processes are summed up, as share of the whole machine
+		totals
+			.plain(" processes matching ")
+			.highlight(truncate_chars(list.totals.name.clone(), 20))
+			.plain("   CPU: ")
+			.highlight(format!("{} %", format_float(list.totals.cpu_usage / cpu.cores.max(1) as f64)))
+			.plain("   RSS: ")
+			.highlight(format!("{} GiB", format_gib(list.totals.rss)))
+	} else {
+		totals
+			.plain(" processes   CPU: ")
+			.highlight(format!("{} %", format_float(calc_cpu_load_percentage(&cpu.total_load))))
+			.plain("   RAM: ")
+			.highlight(format!("{} %", format_float(mem.memory_use())))
+	};
+
+	//cpu usage is given in % of one core, like top does
+	let header_row = vec![pad_left(String::from("PID"), 7), pad_left(String::from("CPU %"), 7),
+		pad_left(String::from("RSS GiB"), 9), pad_left(String::from("NI"), 3), String::from("S"), String::from("NAME")];
+	let mut table = Table::new(header_row, vec![8, 8, 10, 4, 3]);
+	//empty rows below the last process keep the status line at the bottom
+	for index in view.scroll..view.scroll + visible {
+		table = match list.rows.get(index) {
+			Some(row) => {
+				let marker = if row.children == 0 { "  " } else if row.collapsed { "+ " } else { "- " };
+				table.row(vec![pad_left(format!("{}", row.pid), 7), pad_left(format_float(row.cpu_usage), 7),
+					pad_left(format_gib(row.rss), 9), pad_left(format!("{}", row.nice), 3), format!("{}", row.state),
+					format!("{}{}{}", pad_string(String::new(), row.depth * 2), marker, row.name)])
+			},
+			None => table.row(Vec::new())
+		};
+	}
+	if selected >= view.scroll && selected < list.rows.len() {
+		table = table.select(selected - view.scroll);
+	}
+
+	//the last line asks for confirmation, shows the result of an action or the keys
+	let status = if let Some(ref pattern) = view.search {
+		Text::new().highlight(format!("/{}_", pattern))
+	} else if let Some(ref pending) = view.pending {
+		Text::new().highlight(format!("{}? y/n", pending.describe()))
+	} else if let Some(ref message) = view.message {
+		Text::new().highlight(message.clone())
+	} else if tree {
+		Text::new().plain("up/down: select   space: collapse/expand   t/K/s/c: term/kill/stop/cont   n/N: nice +/-   /: search   q: quit")
+	} else {
+		Text::new().plain("up/down: select   t/K/s/c: term/kill/stop/cont   n/N: nice +/-   /: search   q: quit")
+	};
+
+	//nothing is written into the last column of the terminal
+	draw(term, settings, &Layout::Columns(vec![(Constraint::Length(width.saturating_sub(1)), Layout::Rows(vec![
+		header(settings, if tree { "PROCESS TREE" } else { "PROCESSES" }),
+		totals.into(),
+		blank(),
+		table.into(),
+		status.into()
+	]))]));
+}
+
+const LOG_SEPERATOR: &'static str = "    ";
+
 //a one-line version of print that can be used to log the data (-l flag)
-pub fn print_log_mode(mut term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
-                      cpu: &CPUInfo, mem: &MemInfo) {
-	let seperator = "    ";
-    
-	let time = ::time::now();
-	let timestamp = format!("{}m/{}d/{}y-{}h:{}m:{}s",
-         time.tm_mon+1, time.tm_mday, time.tm_year+1900, time.tm_hour, time.tm_min, time.tm_sec);
-	let cpuload_string = format_float(calc_cpu_load_percentage(&cpu.total_load));
-	let mem_string = format_gib(mem.total - mem.free - mem.cached);
-	let swap_string = format_gib(mem.swap_used);
-
-	p!(term, "{}{}CPU:", timestamp, seperator);
-	print_highlighted(term, &settings, format!("{}%", cpuload_string));
-	p!(term, "{}RAM:", seperator);
-	print_highlighted(term, &settings, format!("{}Gib", mem_string));
+pub fn print_log_mode(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
+                      cpu: &CPUInfo, mem: &MemInfo, sections: &Sections) {
+	let timestamp = settings.timestamp.format(::time::get_time(), settings.utc);
+	if let Some(ref timestamp) = timestamp {
+		p!(term, "{}{}", timestamp, LOG_SEPERATOR);
+	}
+	for (index, (label, value)) in log_fields(cpu, mem, sections).into_iter().enumerate() {
+		if index > 0 {
+			p!(term, "{}", LOG_SEPERATOR);
+		}
+		p!(term, "{}:", label);
+		print_highlighted(term, &settings, value);
+	}
+	pl!(term, "");
+}
+
+//the same line without colors, for log files
+pub fn log_line(settings: &Settings, cpu: &CPUInfo, mem: &MemInfo, sections: &Sections) -> String {
+	let mut parts = Vec::new();
+	if let Some(timestamp) = settings.timestamp.format(::time::get_time(), settings.utc) {
+		parts.push(timestamp);
+	}
+	for (label, value) in log_fields(cpu, mem, sections) {
+		parts.push(format!("{}:{}", label, value));
+	}
+	format!("{}\n", parts.join(LOG_SEPERATOR))
+}
+
+//(label, value) of each entry of the log line
+fn log_fields(cpu: &CPUInfo, mem: &MemInfo, sections: &Sections) -> Vec<(&'static str, String)> {
+	let mut fields = Vec::new();
+	fields.push(("CPU", format!("{}%", format_float(calc_cpu_load_percentage(&cpu.total_load)))));
+	fields.push(("RAM", format!("{}Gib", format_gib(mem.total - mem.free - mem.cached))));
 	if mem.swap_used != 0 {
-		p!(term, "{}SWAP:", seperator);
-		print_highlighted(term, &settings, format!("{}Gib", swap_string));
+		fields.push(("SWAP", format!("{}Gib", format_gib(mem.swap_used))));
+	}
+	if let Some(cgroup) = sections.cgroup {
+		fields.push(("CONTAINER-CPU", format!("{}%", format_float(cgroup.cpu_usage))));
+		fields.push(("CONTAINER-RAM", format!("{}Gib", format_gib(cgroup.memory_current))));
+	}
+	if let Some(vmstat) = sections.vmstat {
+		fields.push(("MAJFLT", format_rate(vmstat.major_faults)));
+		fields.push(("SWPIN", format_rate(vmstat.swap_in)));
+		fields.push(("SWPOUT", format_rate(vmstat.swap_out)));
+	}
+	if let Some(power) = sections.power {
+		if !power.batteries.is_empty() {
+			fields.push(("BAT", format!("{}%", format_float(power.capacity()))));
+			if let Some(watts) = power.draw() {
+				fields.push(("DRAW", format!("{:.1}W", watts)));
+			}
+		}
 	}
+	if let Some(pressure) = sections.pressure {
+		fields.push(("PSI-CPU", format!("{}%", format_float(pressure.cpu.some.avg10))));
+		fields.push(("PSI-MEM", format!("{}%", format_float(pressure.memory.some.avg10))));
+		fields.push(("PSI-IO", format!("{}%", format_float(pressure.io.some.avg10))));
+	}
+	fields
+}
 
-	pl!(term, "");
+//min/mean/max/p95 of the whole session, printed on exit
+pub fn print_summary(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
+                     session: &Session) {
+	let header_row = vec!["", "MIN", "MEAN", "MAX", "P95"].into_iter().map(String::from).collect();
+	let mut table = Table::new(header_row, vec![9, 10, 10, 10]);
+	for (name, summary) in session.summaries() {
+		table = table.row(vec![format!("{}:", name), format!("{} %", format_float(summary.min)),
+			format!("{} %", format_float(summary.mean)), format!("{} %", format_float(summary.max)),
+			format!("{} %", format_float(summary.p95))]);
+	}
+	draw(term, settings, &Layout::Rows(vec![
+		header(settings, "SESSION SUMMARY"),
+		Text::new()
+			.highlight(format!("{}", session.samples()))
+			.plain(" samples over ")
+			.highlight(format_duration(session.duration_secs()))
+			.into(),
+		blank(),
+		table.into()
+	]));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn layout_rows() {
+		assert_eq!(parse_layout_row("cpu:60% memory").unwrap(),
+		           vec![(Section::Cpu, Constraint::Percentage(60)), (Section::Memory, Constraint::Fill)]);
+		assert_eq!(parse_layout_row("  graph:80  compact ").unwrap(),
+		           vec![(Section::Graph, Constraint::Length(80)), (Section::Compact, Constraint::Fill)]);
+		assert!(parse_layout_row("cpu disk").unwrap_err().starts_with("unknown section disk"));
+		assert!(parse_layout_row("cpu:0").is_err());
+		assert!(parse_layout_row("cpu:120%").is_err());
+		assert!(parse_layout_row("cpu:wide").is_err());
+		assert!(parse_layout_row(" ").is_err());
+	}
 }
@@ -11,92 +11,69 @@ use term::{
 use Settings;
 
 use std::io::Stdout;
+use std::mem;
+
+use libc;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use cpuinfo::CPULoad;
-use graph::Graph;
+use theme::Color;
+use history::TieredGraph;
+use widgets::{Widget, Text, write_spans};
+
+//loads from 0.0 to 1.0 (set in the config file), also the breakpoints of the gradient
+#[derive(Clone, Copy)]
+pub struct Thresholds {
+	pub warning: f64,
+	pub critical: f64
+}
+
+pub const DEFAULT_THRESHOLDS: Thresholds = Thresholds {
+	warning: 0.6,
+	critical: 0.85
+};
 
 //UI Objects
+//drawn by the widgets, the log mode writes its values in place
 
-//print a progress bar ( -> [======      ] ),
-//where value ranges from 0.0 to 1.0 (panic otherwise)
-pub fn print_progress_bar(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
-                          value: f64, size: usize, color: u32) {
-    assert!(value >= 0.0 && value <= 1.0);
-	let barsize = ((value * size as f64)) as usize;
-	let _ = write!(term, "[");
-	colorize(term, settings, color);
-	attribute(term, settings, Attr::Bold);
-	for i in 0..size {
-		if i < barsize {
-			let _ = write!(term, "=");
-		}
-		else {
-		 	let _ = write!(term, " ");
-		}
+//red from the critical load on, yellow from the warning load on and the given color below
+pub fn gradient_color(thresholds: &Thresholds, value: f64, color: u32) -> u32 {
+	if value >= thresholds.critical {
+		color::RED
+	}
+	else if value >= thresholds.warning {
+		color::YELLOW
+	}
+	else {
+		color
 	}
-	reset(term, settings);
-	let _ = write!(term, "]");
 }
 
 pub fn print_highlighted(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
                          content: String) {
-	colorize(term, settings, color::CYAN);
-	attribute(term, settings, Attr::Bold);
-	let _ = write!(term, "{}", content);
-	reset(term, settings);
-}
-
-pub fn print_header(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings,
-                    size: usize, name: String) {
-	let halfsize = (size - name.len() - 2) / 2;
-	let extend_first = halfsize * 2 + name.len() + 2 != size; //catch rounding errors
-	for _ in 0..halfsize {
-		let _ = write!(term, "=");
-	}
-	if extend_first {
-		let _ = write!(term, "=");
-	}
-	colorize(term, settings, color::YELLOW);
-	attribute(term, settings, Attr::Bold);
-	let _ = write!(term, " {} ", name);
-	reset(term, settings);
-	for _ in 0..halfsize {
-		let _ = write!(term, "=");
-	}
-	let _ = writeln!(term, "");
-}
-
-pub fn print_graph(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings, graph: &Graph) {
-    let graph_height_values = graph.height_values(10);
-    for y in (0..5).rev() {
-        let mut label = format!("{}%", y*25);
-        while label.len() < 5 {
-            label.push(' ');
-        }
-        label.push('|');
-        let _ = write!(term, "{}", label);
-        colorize(term, &settings, color::CYAN);
-        attribute(term, &settings, Attr::Bold);
-        for x in 0..graph_height_values.len() {
-            let size = graph_height_values[x];
-            if size < y*2 {
-                let _ = write!(term, " ");
-            }
-            else if size < y*2 +1 {
-                let _ = write!(term, ".");
-            }
-            else {
-                let _ = write!(term, ":");
-            }
-        }
-        reset(term, &settings);
-        let _ = writeln!(term, "");
-    }
-    let _ = writeln!(term, "");
+	write_spans(term, settings, &Text::new().highlight(content).render(0)[0]);
+}
+
+//the mean of the last minute and the last five minutes ( -> avg 1m / 5m: 12.3 / 10.5 % )
+pub fn averages(graph: &TieredGraph) -> Text {
+	Text::new()
+		.plain("avg 1m / 5m: ")
+		.highlight(format!("{} / {} %", format_float(graph.mean(1)), format_float(graph.mean(5))))
+		.plain("   ")
 }
 
 //HELPER FUNCTIONS
 
+//(columns, rows) of the terminal, 80x24 if it can not be determined
+pub fn terminal_size() -> (usize, usize) {
+	let mut size: libc::winsize = unsafe { mem::zeroed() };
+	let result = unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) };
+	if result != 0 || size.ws_col == 0 || size.ws_row == 0 {
+		return (80, 24);
+	}
+	(size.ws_col as usize, size.ws_row as usize)
+}
+
 pub fn attribute(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings, attrib: Attr) {
 	if settings.enable_color {
 		let _ = term.attr(attrib);
@@ -109,9 +86,19 @@ pub fn reset(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Se
 	}
 }
 
+//one of the basic colors of the bars, as the theme draws it
 pub fn colorize(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings, color_code: u32) {
-	if settings.enable_color {
-		let _ = term.fg(color_code);
+	paint(term, settings, settings.theme.bar(color_code));
+}
+
+//colors the terminal can not show are replaced by the closest one
+pub fn paint(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings, color: Color) {
+	if !settings.enable_color {
+		return;
+	}
+	match color.downgrade(settings.color_depth) {
+		Color::Basic(code) => { let _ = term.fg(code); },
+		color => { let _ = write!(term, "{}", color.escape()); }
 	}
 }
 
@@ -123,13 +110,63 @@ pub fn format_float(float: f64) -> String {
 	string
 }
 
+//pads to `size` columns, wide characters (like CJK) take two
 pub fn pad_string(mut str: String, size: usize) -> String {
-    while str.len() < size {
+    let mut length = UnicodeWidthStr::width(str.as_str());
+    while length < size {
         str.push(' ');
+        length += 1;
     }
     str
 }
 
+//cuts the string after `size` columns, names of processes and users can contain any UTF-8
+pub fn truncate_chars(mut str: String, size: usize) -> String {
+    let mut width = 0;
+    let end = str.char_indices().find(|&(_, ch)| {
+        width += UnicodeWidthChar::width(ch).unwrap_or(0);
+        width > size
+    });
+    if let Some((index, _)) = end {
+        str.truncate(index);
+    }
+    str
+}
+
+//formats seconds as hh:mm:ss
+pub fn format_duration(secs: i64) -> String {
+	format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+pub fn pad_left(str: String, size: usize) -> String {
+    let mut result = String::new();
+    let length = UnicodeWidthStr::width(str.as_str());
+    while result.len() + length < size {
+        result.push(' ');
+    }
+    result + &str
+}
+
+//events per second, without decimals once they do not matter anymore
+pub fn format_rate(rate: f64) -> String {
+	if rate >= 100.0 {
+		format!("{:.0}/s", rate)
+	}
+	else {
+		format!("{:.1}/s", rate)
+	}
+}
+
+//bytes per second
+pub fn format_bytes_rate(rate: f64) -> String {
+	format!("{:.1} MiB/s", rate / 1024.0 / 1024.0)
+}
+
+//values that can not be read (missing permissions) are shown as "-"
+pub fn format_optional(value: Option<String>) -> String {
+	value.unwrap_or(String::from("-"))
+}
+
 //takes kilobytes, transforms to gibibytes and crops the result according to format_float()
 pub fn format_gib(kib: u64) -> String {
 	let gib = ((kib as f64 / 1024.0) / 1024.0) / 1024.0;
@@ -148,3 +185,38 @@ pub fn calc_cpu_load_percentage(load: &CPULoad) -> f64 {
 	}
 	load_percentage
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_counts_columns() {
+        assert_eq!(truncate_chars(String::from("kworker/0:1"), 7), "kworker");
+        assert_eq!(truncate_chars(String::from("größenwahn"), 4), "größ");
+        //wide characters take two columns and are not split
+        assert_eq!(truncate_chars(String::from("日本語のプロセス"), 6), "日本語");
+        assert_eq!(truncate_chars(String::from("日本語のプロセス"), 3), "日");
+        assert_eq!(truncate_chars(String::from("short"), 20), "short");
+        assert_eq!(truncate_chars(String::from("abc"), 0), "");
+    }
+
+    #[test]
+    fn gradient_breakpoints() {
+        let thresholds = Thresholds { warning: 0.5, critical: 0.9 };
+        assert_eq!(gradient_color(&thresholds, 0.49, color::GREEN), color::GREEN);
+        assert_eq!(gradient_color(&thresholds, 0.5, color::GREEN), color::YELLOW);
+        assert_eq!(gradient_color(&thresholds, 0.89, color::CYAN), color::YELLOW);
+        assert_eq!(gradient_color(&thresholds, 0.9, color::CYAN), color::RED);
+        assert_eq!(gradient_color(&DEFAULT_THRESHOLDS, 0.3, color::CYAN), color::CYAN);
+    }
+
+    #[test]
+    fn padding_counts_columns() {
+        assert_eq!(pad_string(String::from("äb"), 4), "äb  ");
+        assert_eq!(pad_left(String::from("äb"), 4), "  äb");
+        assert_eq!(pad_string(String::from("日本"), 6), "日本  ");
+        assert_eq!(pad_left(String::from("日本"), 5), " 日本");
+        assert_eq!(pad_string(String::from("abcdef"), 4), "abcdef");
+    }
+}
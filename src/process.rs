@@ -0,0 +1,494 @@
+/*
+Reads the per-process files in /proc/[pid]
+CPU usage is calculated from the difference of utime/stime between two updates
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use libc;
+use time;
+
+use fsutils::{read_file, read_keyed};
+use history::TieredGraph;
+
+//the fields of /proc/[pid]/stat (or /proc/[pid]/task/[tid]/stat) run-info uses
+pub struct ProcStat {
+	pub comm: String,
+	pub state: char,
+	pub ppid: i32,
+	pub utime: u64, //in clock ticks
+	pub stime: u64,
+	pub cutime: u64, //of the children that exited and were waited for
+	pub cstime: u64,
+	pub nice: i32,
+	pub num_threads: u64,
+	pub starttime: u64, //in clock ticks after boot, tells a process apart from a later one with the same pid
+	pub rss: u64, //in bytes
+	pub processor: i32 //the cpu the process (or thread) last ran on
+}
+
+impl ProcStat {
+	pub fn ticks(&self) -> u64 {
+		self.utime + self.stime
+	}
+
+	pub fn children_ticks(&self) -> u64 {
+		self.cutime + self.cstime
+	}
+}
+
+//parses lines like "1234 (some name) S 1 ...", the name can contain spaces and parentheses
+pub fn read_stat<P: AsRef<Path>>(location: P) -> Result<ProcStat> {
+	let plain = try!(read_file(location));
+	let (open, close) = match (plain.find('('), plain.rfind(')')) {
+		(Some(open), Some(close)) if open < close => (open, close),
+		_ => return Err(Error::new(ErrorKind::InvalidData, "incorrect process stat format"))
+	};
+	let fields: Vec<&str> = plain[close + 1..].split_whitespace().collect();
+	if fields.len() < 37 {
+		return Err(Error::new(ErrorKind::InvalidData, "incorrect process stat format"));
+	}
+	//fields[0] is the 3rd field of the file
+	let number = |index: usize| fields[index].parse::<i64>().unwrap_or(0);
+	Ok(ProcStat {
+		comm: plain[open + 1..close].to_owned(),
+		state: fields[0].chars().next().unwrap_or('?'),
+		ppid: number(1) as i32,
+		utime: number(11) as u64,
+		stime: number(12) as u64,
+		cutime: number(13) as u64,
+		cstime: number(14) as u64,
+		nice: number(16) as i32,
+		num_threads: number(17) as u64,
+		starttime: number(19) as u64,
+		rss: number(21) as u64 * page_size(),
+		processor: number(36) as i32
+	})
+}
+
+//all pids currently in /proc
+pub fn list_pids() -> Result<Vec<i32>> {
+	let mut pids = Vec::new();
+	for entry in try!(fs::read_dir("/proc")) {
+		if let Ok(pid) = try!(entry).file_name().to_string_lossy().parse::<i32>() {
+			pids.push(pid);
+		}
+	}
+	pids.sort();
+	Ok(pids)
+}
+
+//None if the process is gone
+pub fn start_time(pid: i32) -> Option<u64> {
+	read_stat(Path::new("/proc").join(format!("{}", pid)).join("stat")).ok().map(|stat| stat.starttime)
+}
+
+//errors like EPERM (process of another user) or ESRCH (already gone) are returned as they are
+pub fn send_signal(pid: i32, signal: libc::c_int) -> Result<()> {
+	if unsafe { libc::kill(pid, signal) } != 0 {
+		return Err(Error::last_os_error());
+	}
+	Ok(())
+}
+
+//lowering the nice value below the current one needs CAP_SYS_NICE
+pub fn set_nice(pid: i32, nice: i32) -> Result<()> {
+	if unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, nice) } != 0 {
+		return Err(Error::last_os_error());
+	}
+	Ok(())
+}
+
+//the real uid from the "Uid:" line of /proc/[pid]/status
+pub fn read_uid<P: AsRef<Path>>(location: P) -> Result<u32> {
+	for line in try!(read_file(location)).lines() {
+		if line.starts_with("Uid:") {
+			if let Some(Ok(uid)) = line.split_whitespace().nth(1).map(|v| v.parse::<u32>()) {
+				return Ok(uid);
+			}
+		}
+	}
+	Err(Error::new(ErrorKind::InvalidData, "incorrect process status format"))
+}
+
+//the arguments of the process separated by spaces, empty for kernel threads
+pub fn read_cmdline<P: AsRef<Path>>(location: P) -> Result<String> {
+	let plain = try!(fs::read(location));
+	let arguments: Vec<String> = plain.split(|byte| *byte == 0)
+		.filter(|argument| !argument.is_empty())
+		.map(|argument| String::from_utf8_lossy(argument).into_owned())
+		.collect();
+	Ok(arguments.join(" "))
+}
+
+//the cgroup path from /proc/[pid]/cgroup, lines look like "0::/system.slice/sshd.service"
+//the unified (v2) hierarchy is preferred, then the one systemd uses with v1
+pub fn read_cgroup<P: AsRef<Path>>(location: P) -> Result<String> {
+	let plain = try!(read_file(location));
+	let mut paths = Vec::new();
+	for line in plain.lines() {
+		let columns: Vec<&str> = line.splitn(3, ':').collect();
+		if columns.len() == 3 {
+			let rank = match (columns[0], columns[1]) {
+				("0", "") => 0,
+				(_, "name=systemd") => 1,
+				_ => 2
+			};
+			paths.push((rank, columns[2].to_owned()));
+		}
+	}
+	paths.sort_by_key(|&(rank, _)| rank);
+	//processes outside of any group (like kernel threads) are in "/" everywhere
+	match paths.iter().find(|&&(_, ref path)| path != "/") {
+		Some(&(_, ref path)) => Ok(path.clone()),
+		None => Ok(String::from("/"))
+	}
+}
+
+//the systemd unit of a cgroup path ("/system.slice/sshd.service" -> "sshd.service"), otherwise the path itself
+pub fn service_name(cgroup: &str) -> String {
+	for part in cgroup.rsplit('/') {
+		if part.ends_with(".service") || part.ends_with(".scope") {
+			return part.to_owned();
+		}
+	}
+	cgroup.to_owned()
+}
+
+pub fn clock_ticks() -> u64 {
+	let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+	if ticks > 0 { ticks as u64 } else { 100 }
+}
+
+pub fn page_size() -> u64 {
+	let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+	if size > 0 { size as u64 } else { 4096 }
+}
+
+//a thread of a followed process, from /proc/[pid]/task/[tid]
+pub struct ThreadInfo {
+	pub tid: i32,
+	pub name: String,
+	pub state: char,
+	pub cpu_usage: f64, //in cores since the last update
+	pub processor: i32
+}
+
+//the busiest threads first, equally busy ones by their tid
+fn sort_threads(threads: &mut Vec<ThreadInfo>) {
+	threads.sort_by(|a, b| b.cpu_usage.partial_cmp(&a.cpu_usage).expect("invalid cpu usage").then(a.tid.cmp(&b.tid)));
+}
+
+//a process followed with --pid
+pub struct ProcessInfo {
+	pub pid: i32,
+	pub name: String,
+	pub state: char,
+	pub alive: bool, //false once the process exited, even if its pid is reused later
+
+	pub cpu_usage: f64, //in cores since the last update (1.0 = one core fully used)
+	pub rss: u64, //in bytes
+	pub pss: Option<u64>, //None if smaps_rollup is not readable (other users' processes)
+	pub threads: u64,
+	pub fds: Option<usize>,
+	pub read_rate: Option<f64>, //bytes per second from/to the storage layer
+	pub write_rate: Option<f64>,
+
+	pub cpu_history: TieredGraph, //share of the whole machine, 0.0 to 1.0
+	pub thread_list: Vec<ThreadInfo>, //only read with --threads, the busiest first
+
+	starttime: Option<u64>, //from the first read, tells the process apart from a later one with the same pid
+	last_ticks: u64,
+	last_io: Option<(u64, u64)>,
+	last_update_ns: u64,
+	last_thread_ticks: HashMap<i32, u64>,
+	last_threads_ns: u64
+}
+
+impl ProcessInfo {
+	pub fn new(pid: i32) -> ProcessInfo {
+		ProcessInfo {
+			pid: pid,
+			name: String::new(),
+			state: '?',
+			alive: true,
+			cpu_usage: 0.0,
+			rss: 0,
+			pss: None,
+			threads: 0,
+			fds: None,
+			read_rate: None,
+			write_rate: None,
+			cpu_history: TieredGraph::new(),
+			starttime: None,
+			last_ticks: 0,
+			last_io: None,
+			last_update_ns: 0,
+			thread_list: Vec::new(),
+			last_thread_ticks: HashMap::new(),
+			last_threads_ns: 0
+		}
+	}
+
+	//reads the threads, the CPU usage of each is calculated like the one of the whole process
+	pub fn update_threads(&mut self) -> Result<()> {
+		if !self.alive {
+			return Err(Error::new(ErrorKind::NotFound, "process exited"));
+		}
+		let task_dir = Path::new("/proc").join(format!("{}", self.pid)).join("task");
+		let now_ns = time::precise_time_ns();
+		let elapsed = now_ns.saturating_sub(self.last_threads_ns) as f64 / 1000000000.0;
+		let mut threads = Vec::new();
+		let mut ticks = HashMap::new();
+		for entry in try!(fs::read_dir(&task_dir)) {
+			let tid = match try!(entry).file_name().to_string_lossy().parse::<i32>() {
+				Ok(v) => v,
+				Err(_) => continue
+			};
+			//threads can exit while they are read
+			let stat = match read_stat(task_dir.join(format!("{}", tid)).join("stat")) {
+				Ok(v) => v,
+				Err(_) => continue
+			};
+			let cpu_usage = match self.last_thread_ticks.get(&tid) {
+				Some(last) if elapsed > 0.0 => stat.ticks().saturating_sub(*last) as f64 / clock_ticks() as f64 / elapsed,
+				_ => 0.0
+			};
+			ticks.insert(tid, stat.ticks());
+			threads.push(ThreadInfo {
+				tid: tid,
+				name: stat.comm,
+				state: stat.state,
+				cpu_usage: cpu_usage,
+				processor: stat.processor
+			});
+		}
+		sort_threads(&mut threads);
+		self.thread_list = threads;
+		self.last_thread_ticks = ticks;
+		self.last_threads_ns = now_ns;
+		Ok(())
+	}
+
+	//an error means the process is gone, the last values are kept for display
+	pub fn update(&mut self, cores: usize) -> Result<()> {
+		if !self.alive {
+			return Err(Error::new(ErrorKind::NotFound, "process exited"));
+		}
+		let dir = Path::new("/proc").join(format!("{}", self.pid));
+		let now_ns = time::precise_time_ns();
+		let stat = match read_stat(dir.join("stat")) {
+			Ok(v) => v,
+			Err(err) => {
+				self.alive = false;
+				self.state = 'X';
+				return Err(err);
+			}
+		};
+		//the pid was reused by another process since the last update
+		if self.starttime.map(|starttime| starttime != stat.starttime).unwrap_or(false) {
+			self.alive = false;
+			self.state = 'X';
+			return Err(Error::new(ErrorKind::NotFound, "process exited"));
+		}
+		self.starttime = Some(stat.starttime);
+		let elapsed = now_ns.saturating_sub(self.last_update_ns) as f64 / 1000000000.0;
+		let first_update = self.last_update_ns == 0;
+
+		self.name = stat.comm.clone();
+		self.state = stat.state;
+		self.threads = stat.num_threads;
+		self.rss = stat.rss;
+		if !first_update && elapsed > 0.0 {
+			let ticks = stat.ticks().saturating_sub(self.last_ticks);
+			self.cpu_usage = ticks as f64 / clock_ticks() as f64 / elapsed;
+			self.cpu_history.push((self.cpu_usage / cores.max(1) as f64).min(1.0));
+		}
+		self.last_ticks = stat.ticks();
+
+		//these need ptrace access, so they are often missing for processes of other users
+		let rollup = read_keyed(dir.join("smaps_rollup")).ok();
+		self.pss = rollup.as_ref().and_then(|map| map.get("Pss:").cloned()).map(|kib| kib * 1024);
+		if let Some(rss) = rollup.as_ref().and_then(|map| map.get("Rss:").cloned()) {
+			self.rss = rss * 1024;
+		}
+		self.fds = fs::read_dir(dir.join("fd")).ok().map(|entries| entries.count());
+		let io = read_keyed(dir.join("io")).ok().and_then(|map| {
+			match (map.get("read_bytes:"), map.get("write_bytes:")) {
+				(Some(read), Some(write)) => Some((*read, *write)),
+				_ => None
+			}
+		});
+		match (io, self.last_io) {
+			(Some((read, write)), Some((last_read, last_write))) if elapsed > 0.0 => {
+				self.read_rate = Some(read.saturating_sub(last_read) as f64 / elapsed);
+				self.write_rate = Some(write.saturating_sub(last_write) as f64 / elapsed);
+			},
+			(None, _) => {
+				self.read_rate = None;
+				self.write_rate = None;
+			},
+			_ => {}
+		}
+		self.last_io = io;
+		self.last_update_ns = now_ns;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::process::Command;
+	use std::sync::mpsc;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn exited_process_is_not_followed_anymore() {
+		let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+		let mut process = ProcessInfo::new(child.id() as i32);
+		process.update(1).unwrap();
+		assert!(process.alive);
+		child.kill().unwrap();
+		child.wait().unwrap();
+		assert!(process.update(1).is_err());
+		assert!(!process.alive);
+		assert_eq!(process.state, 'X');
+		assert_eq!(process.name, "sleep");
+	}
+
+	#[test]
+	fn threads_of_a_process() {
+		//a single threaded child: its only thread has the pid as tid
+		let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+		let mut process = ProcessInfo::new(child.id() as i32);
+		//until the child has executed sleep and waits
+		for _ in 0..100 {
+			process.update_threads().unwrap();
+			if process.thread_list.iter().all(|thread| thread.name == "sleep" && thread.state == 'S') {
+				break;
+			}
+			thread::sleep(Duration::from_millis(10));
+		}
+		child.kill().unwrap();
+		child.wait().unwrap();
+		assert_eq!(process.thread_list.len(), 1);
+		let thread = &process.thread_list[0];
+		assert_eq!((thread.tid, thread.name.as_str(), thread.state), (child.id() as i32, "sleep", 'S'));
+		assert!(thread.processor >= 0);
+		assert!(thread.cpu_usage >= 0.0);
+
+		//a thread of the test process, waiting on a channel
+		let (tid_sender, tid_receiver) = mpsc::channel();
+		let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+		let waiting = thread::spawn(move || {
+			tid_sender.send(unsafe { libc::syscall(libc::SYS_gettid) } as i32).unwrap();
+			let _ = stop_receiver.recv();
+		});
+		let tid = tid_receiver.recv().unwrap();
+		//give it time to block in recv()
+		for _ in 0..100 {
+			let mut process = ProcessInfo::new(::std::process::id() as i32);
+			process.update_threads().unwrap();
+			if process.thread_list.iter().any(|thread| thread.tid == tid && thread.state == 'S') {
+				break;
+			}
+			thread::sleep(Duration::from_millis(10));
+		}
+		let mut process = ProcessInfo::new(::std::process::id() as i32);
+		process.update_threads().unwrap();
+		stop_sender.send(()).unwrap();
+		waiting.join().unwrap();
+		assert!(process.thread_list.iter().any(|thread| thread.tid == ::std::process::id() as i32));
+		let thread = process.thread_list.iter().find(|thread| thread.tid == tid).expect("thread is not listed");
+		assert_eq!(thread.state, 'S');
+	}
+
+	fn thread_info(tid: i32, cpu_usage: f64) -> ThreadInfo {
+		ThreadInfo {
+			tid: tid,
+			name: String::from("worker"),
+			state: 'R',
+			cpu_usage: cpu_usage,
+			processor: 0
+		}
+	}
+
+	#[test]
+	fn busiest_threads_first() {
+		let mut threads = vec![thread_info(12, 0.1), thread_info(11, 0.0), thread_info(13, 0.9), thread_info(10, 0.1)];
+		sort_threads(&mut threads);
+		//equally busy threads are ordered by their tid
+		assert_eq!(threads.iter().map(|thread| thread.tid).collect::<Vec<i32>>(), vec![13, 10, 12, 11]);
+	}
+
+	#[test]
+	fn reused_pid_is_detected() {
+		let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+		let mut process = ProcessInfo::new(child.id() as i32);
+		process.update(1).unwrap();
+		//as if another process had been started with the same pid
+		process.starttime = process.starttime.map(|starttime| starttime + 1);
+		assert!(process.update(1).is_err());
+		assert!(!process.alive);
+		child.kill().unwrap();
+		child.wait().unwrap();
+	}
+
+	#[test]
+	fn stat_with_spaces_and_parentheses_in_the_name() {
+		let stat = read_stat(format!("/proc/{}/stat", ::std::process::id())).unwrap();
+		assert!(stat.ticks() >= stat.utime);
+		let dir = temp_dir("stat");
+		let path = dir.join("stat");
+		fs::write(&path, "42 (a (b) c) S 1 42 42 0 -1 0 0 0 0 0 7 3 0 0 20 5 2 0 12345 0 10 0 0 0 0 0 0 0 0 0 0 0 0 0 0 3\n").unwrap();
+		let stat = read_stat(&path).unwrap();
+		let _ = fs::remove_dir_all(&dir);
+		assert_eq!(stat.comm, "a (b) c");
+		assert_eq!((stat.state, stat.ppid, stat.utime, stat.stime), ('S', 1, 7, 3));
+		assert_eq!((stat.cutime, stat.cstime), (0, 0));
+		assert_eq!((stat.nice, stat.num_threads, stat.starttime), (5, 2, 12345));
+		assert_eq!(stat.rss, 10 * page_size());
+		assert_eq!(stat.processor, 3);
+	}
+
+	#[test]
+	fn cgroup_of_the_unified_hierarchy_is_preferred() {
+		let dir = temp_dir("cgroup");
+		let path = dir.join("cgroup");
+		fs::write(&path, "12:cpu,cpuacct:/docker/abc\n1:name=systemd:/system.slice/docker.service\n0::/system.slice/sshd.service\n").unwrap();
+		assert_eq!(read_cgroup(&path).unwrap(), "/system.slice/sshd.service");
+		//with cgroup v1 the systemd hierarchy is used
+		fs::write(&path, "12:cpu,cpuacct:/docker/abc\n1:name=systemd:/system.slice/docker.service\n0::/\n").unwrap();
+		assert_eq!(read_cgroup(&path).unwrap(), "/system.slice/docker.service");
+		fs::write(&path, "0::/\n").unwrap();
+		assert_eq!(read_cgroup(&path).unwrap(), "/");
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn service_names() {
+		assert_eq!(service_name("/system.slice/sshd.service"), "sshd.service");
+		assert_eq!(service_name("/user.slice/user-1000.slice/session-3.scope"), "session-3.scope");
+		assert_eq!(service_name("/system.slice/docker.service/abc"), "docker.service");
+		assert_eq!(service_name("/docker/abc"), "/docker/abc");
+	}
+
+	#[test]
+	fn uid_from_status() {
+		assert!(read_uid(format!("/proc/{}/status", ::std::process::id())).is_ok());
+		assert!(read_uid("/proc/self/nonexistent").is_err());
+	}
+
+	//one directory per test, they run in parallel
+	fn temp_dir(test: &str) -> ::std::path::PathBuf {
+		let dir = ::std::env::temp_dir().join(format!("run-info-process-{}-{}", test, ::std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+}
@@ -0,0 +1,855 @@
+/*
+A table of all processes, shown as a flat list or as a tree grouped by parent pid
+In the tree every process shows the summed up usage of its whole subtree
+The processes can also be summed up by their user or their service (cgroup)
+A filter (regex on the name, command line, user or cgroup) hides the other processes from all of these
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::io::Result;
+use std::path::Path;
+
+use libc;
+use regex::Regex;
+use time;
+
+use input::Key;
+use process::{self, read_stat, ProcStat};
+use users::UserNames;
+
+//rows moved by a step of the mouse wheel
+const WHEEL_ROWS: usize = 3;
+
+pub struct ProcessEntry {
+	pub pid: i32,
+	pub ppid: i32,
+	pub name: String,
+	pub state: char,
+	pub nice: i32,
+	pub starttime: u64,
+	pub cpu_usage: f64, //in cores since the last update
+	pub exited_children_usage: f64, //of the children that exited since the last update, in cores
+	pub rss: u64, //in bytes
+	pub uid: Option<u32>, //None if the status could not be read (process exited, hidepid)
+	pub cmdline: String, //empty for kernel threads
+	pub cgroup: String
+}
+
+pub struct ProcessTable {
+	pub entries: HashMap<i32, ProcessEntry>,
+	pub filter: Option<Regex>,
+	names: UserNames,
+	last_ticks: HashMap<i32, Ticks>,
+	last_update_ns: u64
+}
+
+//what is read from /proc/[pid] for each process
+struct Sample {
+	pid: i32,
+	stat: ProcStat,
+	uid: Option<u32>,
+	cmdline: String,
+	cgroup: String
+}
+
+//the counters of a process at the last update
+struct Ticks {
+	ppid: i32,
+	starttime: u64,
+	own: u64,
+	children: u64
+}
+
+//a line of the table
+pub struct Row {
+	pub pid: i32,
+	pub depth: usize, //always 0 in the flat list
+	pub name: String,
+	pub state: char,
+	pub nice: i32,
+	pub starttime: u64,
+	pub cpu_usage: f64, //of the whole subtree in the tree
+	pub rss: u64,
+	pub children: usize,
+	pub collapsed: bool
+}
+
+//the summed up usage of all processes of a user or service
+pub struct Group {
+	pub name: String,
+	pub processes: usize,
+	pub cpu_usage: f64, //in cores
+	pub rss: u64
+}
+
+impl ProcessTable {
+	pub fn new() -> ProcessTable {
+		ProcessTable::with_names(UserNames::load())
+	}
+
+	pub fn with_names(names: UserNames) -> ProcessTable {
+		ProcessTable {
+			entries: HashMap::new(),
+			filter: None,
+			names: names,
+			last_ticks: HashMap::new(),
+			last_update_ns: 0
+		}
+	}
+
+	pub fn update(&mut self) -> Result<()> {
+		let mut processes = Vec::new();
+		for pid in try!(process::list_pids()) {
+			//processes can exit while the table is read
+			let dir = Path::new("/proc").join(format!("{}", pid));
+			let stat = match read_stat(dir.join("stat")) {
+				Ok(v) => v,
+				Err(_) => continue
+			};
+			processes.push(Sample {
+				pid: pid,
+				stat: stat,
+				uid: process::read_uid(dir.join("status")).ok(),
+				cmdline: process::read_cmdline(dir.join("cmdline")).unwrap_or(String::new()),
+				cgroup: process::read_cgroup(dir.join("cgroup")).unwrap_or(String::from("/"))
+			});
+		}
+		self.update_from(processes, time::precise_time_ns());
+		Ok(())
+	}
+
+	fn update_from(&mut self, processes: Vec<Sample>, now_ns: u64) {
+		let elapsed = now_ns.saturating_sub(self.last_update_ns) as f64 / 1000000000.0;
+		let ticks_per_second = process::clock_ticks() as f64;
+		let usage = |ticks: u64| if elapsed > 0.0 { ticks as f64 / ticks_per_second / elapsed } else { 0.0 };
+
+		//a child that exits adds its whole lifetime to the cutime/cstime of its parent,
+		//the part that was already seen while it was running must not be counted twice
+		let mut ticks = HashMap::new();
+		for sample in &processes {
+			ticks.insert(sample.pid, Ticks {
+				ppid: sample.stat.ppid,
+				starttime: sample.stat.starttime,
+				own: sample.stat.ticks(),
+				children: sample.stat.children_ticks()
+			});
+		}
+		let mut seen_exited: HashMap<i32, u64> = HashMap::new();
+		for (pid, last) in &self.last_ticks {
+			let exited = match ticks.get(pid) {
+				Some(current) => current.starttime != last.starttime,
+				None => true
+			};
+			if exited {
+				*seen_exited.entry(last.ppid).or_insert(0) += last.own + last.children;
+			}
+		}
+
+		let mut entries = HashMap::new();
+		for Sample {pid, stat, uid, cmdline, cgroup} in processes {
+			let (mut cpu_usage, mut exited_children_usage) = (0.0, 0.0);
+			match self.last_ticks.get(&pid) {
+				Some(last) if last.starttime == stat.starttime => {
+					cpu_usage = usage(stat.ticks().saturating_sub(last.own));
+					let children = stat.children_ticks().saturating_sub(last.children);
+					exited_children_usage = usage(children.saturating_sub(seen_exited.get(&pid).cloned().unwrap_or(0)));
+				},
+				_ => {}
+			}
+			entries.insert(pid, ProcessEntry {
+				pid: pid,
+				ppid: stat.ppid,
+				name: stat.comm,
+				state: stat.state,
+				nice: stat.nice,
+				starttime: stat.starttime,
+				cpu_usage: cpu_usage,
+				exited_children_usage: exited_children_usage,
+				rss: stat.rss,
+				uid: uid,
+				cmdline: cmdline,
+				cgroup: cgroup
+			});
+		}
+		self.entries = entries;
+		self.last_ticks = ticks;
+		self.last_update_ns = now_ns;
+	}
+
+	fn user_name(&self, entry: &ProcessEntry) -> String {
+		//processes of unknown owners are not counted for root
+		match entry.uid {
+			Some(uid) => self.names.name(uid),
+			None => String::from("?")
+		}
+	}
+
+	//the name, command line, user or cgroup matches the filter
+	pub fn matches(&self, entry: &ProcessEntry) -> bool {
+		match self.filter {
+			Some(ref regex) => regex.is_match(&entry.name) || regex.is_match(&entry.cmdline)
+				|| regex.is_match(&self.user_name(entry)) || regex.is_match(&entry.cgroup),
+			None => true
+		}
+	}
+
+	//the processes matching the filter, by pid
+	fn visible(&self) -> HashMap<i32, &ProcessEntry> {
+		self.entries.iter().filter(|&(_, entry)| self.matches(entry)).map(|(pid, entry)| (*pid, entry)).collect()
+	}
+
+	//the summed up usage of all processes matching the filter
+	pub fn totals(&self) -> Group {
+		let visible = self.visible();
+		Group {
+			name: match self.filter {
+				Some(ref regex) => regex.as_str().to_owned(),
+				None => String::from("all")
+			},
+			processes: visible.len(),
+			cpu_usage: visible.values().map(|entry| entry.cpu_usage).sum(),
+			rss: visible.values().map(|entry| entry.rss).sum()
+		}
+	}
+
+	//all processes, the busiest first
+	pub fn flat_rows(&self) -> Vec<Row> {
+		let mut rows: Vec<Row> = self.visible().values().map(|entry| Row {
+			pid: entry.pid,
+			depth: 0,
+			name: entry.name.clone(),
+			state: entry.state,
+			nice: entry.nice,
+			starttime: entry.starttime,
+			cpu_usage: entry.cpu_usage,
+			rss: entry.rss,
+			children: 0,
+			collapsed: false
+		}).collect();
+		rows.sort_by(|a, b| b.cpu_usage.partial_cmp(&a.cpu_usage).expect("invalid cpu usage").then(a.pid.cmp(&b.pid)));
+		rows
+	}
+
+	//depth-first by pid, the children of collapsed processes are left out
+	pub fn tree_rows(&self, collapsed: &HashSet<i32>) -> Vec<Row> {
+		let visible = self.visible();
+		let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+		let mut roots = Vec::new();
+		for entry in visible.values() {
+			//processes whose parent is not visible (kernel threads, other pid namespaces, filtered) become roots
+			if entry.ppid != entry.pid && visible.contains_key(&entry.ppid) {
+				children.entry(entry.ppid).or_insert(Vec::new()).push(entry.pid);
+			}
+			else {
+				roots.push(entry.pid);
+			}
+		}
+		for list in children.values_mut() {
+			list.sort();
+		}
+		roots.sort();
+
+		let mut totals = HashMap::new();
+		for root in &roots {
+			sum_subtree(&visible, *root, &children, &mut totals);
+		}
+
+		let mut rows = Vec::new();
+		let mut stack: Vec<(i32, usize)> = roots.iter().rev().map(|pid| (*pid, 0)).collect();
+		while let Some((pid, depth)) = stack.pop() {
+			let entry = visible[&pid];
+			let (cpu_usage, rss) = totals[&pid];
+			let own_children = children.get(&pid).map(|list| list.len()).unwrap_or(0);
+			let is_collapsed = collapsed.contains(&pid);
+			rows.push(Row {
+				pid: pid,
+				depth: depth,
+				name: entry.name.clone(),
+				state: entry.state,
+				nice: entry.nice,
+				starttime: entry.starttime,
+				cpu_usage: cpu_usage,
+				rss: rss,
+				children: own_children,
+				collapsed: is_collapsed
+			});
+			if !is_collapsed {
+				if let Some(list) = children.get(&pid) {
+					for child in list.iter().rev() {
+						stack.push((*child, depth + 1));
+					}
+				}
+			}
+		}
+		rows
+	}
+
+	pub fn by_user(&self) -> Vec<Group> {
+		self.groups(|entry| self.user_name(entry))
+	}
+
+	pub fn by_service(&self) -> Vec<Group> {
+		self.groups(|entry| process::service_name(&entry.cgroup))
+	}
+
+	//sums up the processes with the same key, the busiest group first
+	fn groups<F: Fn(&ProcessEntry) -> String>(&self, key: F) -> Vec<Group> {
+		let mut groups: HashMap<String, Group> = HashMap::new();
+		for entry in self.visible().values() {
+			let name = key(entry);
+			let group = groups.entry(name.clone()).or_insert(Group {
+				name: name,
+				processes: 0,
+				cpu_usage: 0.0,
+				rss: 0
+			});
+			group.processes += 1;
+			group.cpu_usage += entry.cpu_usage;
+			group.rss += entry.rss;
+		}
+		let mut groups: Vec<Group> = groups.into_iter().map(|(_, group)| group).collect();
+		groups.sort_by(|a, b| b.cpu_usage.partial_cmp(&a.cpu_usage).expect("invalid cpu usage")
+			.then(b.rss.cmp(&a.rss)).then(a.name.cmp(&b.name)));
+		groups
+	}
+}
+
+//fills totals with the summed up (cpu usage, rss) of the subtree of every process below pid
+fn sum_subtree(entries: &HashMap<i32, &ProcessEntry>, pid: i32, children: &HashMap<i32, Vec<i32>>,
+               totals: &mut HashMap<i32, (f64, u64)>) -> (f64, u64) {
+	let entry = entries[&pid];
+	//children that exited since the last update are not in the table anymore, their usage is counted here
+	let mut total = (entry.cpu_usage + entry.exited_children_usage, entry.rss);
+	if let Some(list) = children.get(&pid) {
+		for child in list {
+			let (cpu_usage, rss) = sum_subtree(entries, *child, children, totals);
+			total.0 += cpu_usage;
+			total.1 += rss;
+		}
+	}
+	totals.insert(pid, total);
+	total
+}
+
+//what can be done to the selected process, always confirmed first
+#[derive(Clone, Copy)]
+pub enum Action {
+	Signal(libc::c_int),
+	Renice(i32) //the new nice value
+}
+
+impl Action {
+	pub fn describe(&self, pid: i32, name: &str) -> String {
+		match *self {
+			Action::Signal(signal) => format!("send {} to {} ({})", signal_name(signal), pid, name),
+			Action::Renice(nice) => format!("set the nice value of {} ({}) to {}", pid, name, nice)
+		}
+	}
+
+	fn run(&self, pid: i32) -> Result<()> {
+		match *self {
+			Action::Signal(signal) => process::send_signal(pid, signal),
+			Action::Renice(nice) => process::set_nice(pid, nice)
+		}
+	}
+}
+
+fn signal_name(signal: libc::c_int) -> &'static str {
+	match signal {
+		libc::SIGTERM => "SIGTERM",
+		libc::SIGKILL => "SIGKILL",
+		libc::SIGSTOP => "SIGSTOP",
+		libc::SIGCONT => "SIGCONT",
+		_ => "a signal"
+	}
+}
+
+//an action waiting for confirmation
+pub struct Pending {
+	pub pid: i32,
+	pub starttime: u64, //the pid may belong to another process once the action is confirmed
+	pub name: String,
+	pub action: Action
+}
+
+impl Pending {
+	pub fn describe(&self) -> String {
+		self.action.describe(self.pid, &self.name)
+	}
+
+	//runs the action if the pid still belongs to the same process, returns the message to show
+	pub fn confirm(&self) -> String {
+		if process::start_time(self.pid) != Some(self.starttime) {
+			return format!("error: could not {}: process is gone", self.describe());
+		}
+		match self.action.run(self.pid) {
+			Ok(_) => format!("done: {}", self.describe()),
+			Err(err) => format!("error: could not {}: {}", self.describe(), err)
+		}
+	}
+}
+
+//selection, scroll position and collapsed subtrees of the process views
+pub struct ProcessView {
+	pub selected: Option<i32>, //pid of the selected row
+	pub scroll: usize, //index of the first visible row
+	pub collapsed: HashSet<i32>,
+	pub pending: Option<Pending>,
+	pub message: Option<String>, //result of the last action, shown until the next key press
+	pub search: Option<String>, //the pattern being typed after '/'
+	new_filter: Option<Option<Regex>> //a confirmed search, taken by the main loop
+}
+
+impl ProcessView {
+	pub fn new() -> ProcessView {
+		ProcessView {
+			selected: None,
+			scroll: 0,
+			collapsed: HashSet::new(),
+			pending: None,
+			message: None,
+			search: None,
+			new_filter: None
+		}
+	}
+
+	//index of the selected row, the first row if the selected process is gone
+	pub fn selected_index(&self, rows: &[Row]) -> usize {
+		match self.selected {
+			Some(pid) => rows.iter().position(|row| row.pid == pid).unwrap_or(0),
+			None => 0
+		}
+	}
+
+	//moves the scroll position so that the selected row is one of the `visible` rows
+	pub fn scroll_to_selection(&mut self, rows: &[Row], visible: usize) {
+		let index = self.selected_index(rows);
+		if index < self.scroll {
+			self.scroll = index;
+		}
+		else if visible > 0 && index >= self.scroll + visible {
+			self.scroll = index + 1 - visible;
+		}
+		self.scroll = self.scroll.min(rows.len().saturating_sub(visible.max(1)));
+	}
+
+	//a click on a row selects it, but cancels a pending action like any other key
+	pub fn click(&mut self, rows: &[Row], index: usize) {
+		self.message = None;
+		if self.pending.take().is_some() {
+			self.message = Some(String::from("cancelled"));
+			return;
+		}
+		if let Some(row) = rows.get(index) {
+			self.selected = Some(row.pid);
+		}
+	}
+
+	//the mouse wheel moves the visible rows, the selection is kept on screen
+	pub fn scroll_by(&mut self, rows: &[Row], visible: usize, down: bool) {
+		if rows.is_empty() {
+			return;
+		}
+		let visible = visible.max(1);
+		self.scroll = if down {
+			(self.scroll + WHEEL_ROWS).min(rows.len().saturating_sub(visible))
+		} else {
+			self.scroll.saturating_sub(WHEEL_ROWS)
+		};
+		let index = self.selected_index(rows).max(self.scroll).min(self.scroll + visible - 1).min(rows.len() - 1);
+		self.selected = Some(rows[index].pid);
+	}
+
+	//the filter of the last confirmed search, Some(None) if it was cleared
+	pub fn take_filter(&mut self) -> Option<Option<Regex>> {
+		self.new_filter.take()
+	}
+
+	//keys while typing a search pattern, an empty pattern clears the filter
+	fn handle_search_key(&mut self, key: Key, mut pattern: String) {
+		match key {
+			Key::Char(c) => pattern.push(c),
+			Key::Backspace => { pattern.pop(); },
+			Key::Escape => return,
+			Key::Enter if pattern.is_empty() => {
+				self.new_filter = Some(None);
+				return;
+			},
+			Key::Enter => {
+				match Regex::new(&pattern) {
+					Ok(regex) => self.new_filter = Some(Some(regex)),
+					Err(err) => self.message = Some(format!("error: invalid pattern: {}", err))
+				}
+				return;
+			},
+			_ => {}
+		}
+		self.search = Some(pattern);
+	}
+
+	//returns true if the key was used
+	pub fn handle_key(&mut self, key: Key, rows: &[Row], page: usize) -> bool {
+		self.message = None;
+		if let Some(pattern) = self.search.take() {
+			self.handle_search_key(key, pattern);
+			return true;
+		}
+		if key == Key::Char('/') {
+			self.search = Some(String::new());
+			return true;
+		}
+		if let Some(pending) = self.pending.take() {
+			self.message = match key {
+				Key::Char('y') | Key::Char('Y') => Some(pending.confirm()),
+				_ => Some(String::from("cancelled"))
+			};
+			return true;
+		}
+		if rows.is_empty() {
+			return false;
+		}
+		let index = self.selected_index(rows);
+		let action = match key {
+			Key::Char('t') => Some(Action::Signal(libc::SIGTERM)),
+			Key::Char('K') => Some(Action::Signal(libc::SIGKILL)),
+			Key::Char('s') => Some(Action::Signal(libc::SIGSTOP)),
+			Key::Char('c') => Some(Action::Signal(libc::SIGCONT)),
+			Key::Char('n') => Some(Action::Renice((rows[index].nice + 1).min(19))),
+			Key::Char('N') => Some(Action::Renice((rows[index].nice - 1).max(-20))),
+			_ => None
+		};
+		if let Some(action) = action {
+			self.pending = Some(Pending {
+				pid: rows[index].pid,
+				starttime: rows[index].starttime,
+				name: rows[index].name.clone(),
+				action: action
+			});
+			return true;
+		}
+		let last = rows.len() - 1;
+		let new_index = match key {
+			Key::Up | Key::Char('k') => index.saturating_sub(1),
+			Key::Down | Key::Char('j') => (index + 1).min(last),
+			Key::PageUp => index.saturating_sub(page.max(1)),
+			Key::PageDown => (index + page.max(1)).min(last),
+			Key::Home => 0,
+			Key::End => last,
+			Key::Enter | Key::Char(' ') => {
+				let pid = rows[index].pid;
+				if !self.collapsed.remove(&pid) && rows[index].children > 0 {
+					self.collapsed.insert(pid);
+				}
+				index
+			},
+			_ => return false
+		};
+		self.selected = Some(rows[new_index].pid);
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::os::unix::process::ExitStatusExt;
+	use std::process::{Child, Command};
+	use std::thread;
+	use std::time::Duration;
+
+	fn spawn_sleep() -> Child {
+		Command::new("sleep").arg("30").spawn().expect("could not spawn sleep")
+	}
+
+	fn state(pid: i32) -> char {
+		read_stat(format!("/proc/{}/stat", pid)).expect("process is gone").state
+	}
+
+	//signals are delivered asynchronously
+	fn wait_for_state(pid: i32, expected: char) -> char {
+		for _ in 0..100 {
+			if state(pid) == expected {
+				break;
+			}
+			thread::sleep(Duration::from_millis(10));
+		}
+		state(pid)
+	}
+
+	fn pending(pid: i32, starttime: u64, action: Action) -> Pending {
+		Pending {
+			pid: pid,
+			starttime: starttime,
+			name: String::from("sleep"),
+			action: action
+		}
+	}
+
+	fn stat(ppid: i32, own: u64, children: u64) -> ProcStat {
+		ProcStat {
+			comm: String::from("make"),
+			state: 'S',
+			ppid: ppid,
+			utime: own,
+			stime: 0,
+			cutime: children,
+			cstime: 0,
+			nice: 0,
+			num_threads: 1,
+			starttime: 1,
+			rss: 0,
+			processor: 0
+		}
+	}
+
+	fn sample(pid: i32, stat: ProcStat, uid: Option<u32>, cgroup: &str) -> Sample {
+		Sample {
+			pid: pid,
+			stat: stat,
+			uid: uid,
+			cmdline: String::new(),
+			cgroup: String::from(cgroup)
+		}
+	}
+
+	fn subtree_usage(table: &ProcessTable, pid: i32) -> f64 {
+		table.tree_rows(&HashSet::new()).iter().find(|row| row.pid == pid).unwrap().cpu_usage
+	}
+
+	#[test]
+	fn exited_children_count_for_the_parent() {
+		let ticks = process::clock_ticks();
+		let second = 1000000000;
+		let mut table = ProcessTable::with_names(UserNames::from_passwd(""));
+		//a child that is seen running for a while
+		table.update_from(vec![sample(10, stat(1, 0, 0), None, "/"), sample(11, stat(10, 0, 0), None, "/")], second);
+		table.update_from(vec![sample(10, stat(1, 0, 0), None, "/"), sample(11, stat(10, ticks, 0), None, "/")], 2 * second);
+		assert!((subtree_usage(&table, 10) - 1.0).abs() < 1e-9);
+		//it exits after another half second of work, and a short-lived one used a quarter second between the updates
+		let children = ticks + ticks / 2 + ticks / 4;
+		table.update_from(vec![sample(10, stat(1, 0, children), None, "/")], 3 * second);
+		let expected = (ticks / 2 + ticks / 4) as f64 / ticks as f64;
+		assert!((subtree_usage(&table, 10) - expected).abs() < 1e-9, "{}", subtree_usage(&table, 10));
+		//the flat list only shows the usage of the process itself
+		assert_eq!(table.flat_rows()[0].cpu_usage, 0.0);
+	}
+
+	//init, two processes of alice in a session, nginx and a process whose owner is unknown
+	//each of them except init and the unknown one used `own` ticks
+	fn machine(own: u64) -> Vec<Sample> {
+		let mut postgres = sample(21, stat(20, own, 0), Some(1000), "/user.slice/user-1000.slice/session-2.scope");
+		postgres.cmdline = String::from("postgres: alice db [local] idle");
+		vec![
+			sample(1, stat(0, 0, 0), Some(0), "/init.scope"),
+			sample(20, stat(1, own, 0), Some(1000), "/user.slice/user-1000.slice/session-2.scope"),
+			postgres,
+			sample(30, stat(1, own, 0), Some(1001), "/system.slice/nginx.service"),
+			sample(40, stat(1, 0, 0), None, "/")
+		]
+	}
+
+	fn passwd() -> UserNames {
+		UserNames::from_passwd("root:x:0:0:root:/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\n")
+	}
+
+	#[test]
+	fn groups_by_user_and_service() {
+		let ticks = process::clock_ticks();
+		let names = passwd();
+		let mut table = ProcessTable::with_names(names);
+		table.update_from(machine(0), 1000000000);
+		table.update_from(machine(ticks), 2000000000);
+
+		let users = table.by_user();
+		let summary: Vec<(&str, usize, f64)> = users.iter()
+			.map(|group| (group.name.as_str(), group.processes, group.cpu_usage))
+			.collect();
+		assert_eq!(summary, vec![("alice", 2, 2.0), ("1001", 1, 1.0), ("?", 1, 0.0), ("root", 1, 0.0)]);
+
+		let services: Vec<String> = table.by_service().into_iter().map(|group| group.name).collect();
+		assert_eq!(services, vec!["session-2.scope", "nginx.service", "/", "init.scope"]);
+	}
+
+	fn filtered(pattern: &str) -> ProcessTable {
+		let ticks = process::clock_ticks();
+		let mut table = ProcessTable::with_names(passwd());
+		table.update_from(machine(0), 1000000000);
+		table.update_from(machine(ticks), 2000000000);
+		table.filter = Some(Regex::new(pattern).unwrap());
+		table
+	}
+
+	fn pids(rows: &[Row]) -> Vec<i32> {
+		rows.iter().map(|row| row.pid).collect()
+	}
+
+	#[test]
+	fn filter_matches_every_field() {
+		//the name of all test processes is "make"
+		assert_eq!(filtered("^make$").totals().processes, 5);
+		//command line
+		assert_eq!(pids(&filtered("postgres").flat_rows()), vec![21]);
+		//user
+		assert_eq!(pids(&filtered("^alice$").flat_rows()), vec![20, 21]);
+		//cgroup
+		assert_eq!(pids(&filtered("nginx").flat_rows()), vec![30]);
+		assert!(filtered("nothing").flat_rows().is_empty());
+	}
+
+	#[test]
+	fn totals_only_count_matching_processes() {
+		let table = filtered("alice|nginx");
+		let totals = table.totals();
+		assert_eq!((totals.name.as_str(), totals.processes, totals.cpu_usage), ("alice|nginx", 3, 3.0));
+		let users: Vec<String> = table.by_user().into_iter().map(|group| group.name).collect();
+		assert_eq!(users, vec!["alice", "1001"]);
+	}
+
+	#[test]
+	fn filtered_tree_starts_at_matching_processes() {
+		//postgres (21) is a child of 20, which does not match, so it becomes a root
+		let table = filtered("postgres|nginx");
+		let rows = table.tree_rows(&HashSet::new());
+		assert_eq!(pids(&rows), vec![21, 30]);
+		assert!(rows.iter().all(|row| row.depth == 0 && row.cpu_usage == 1.0));
+	}
+
+	#[test]
+	fn search_sets_the_filter() {
+		let mut view = ProcessView::new();
+		for key in vec![Key::Char('/'), Key::Char('p'), Key::Char('x'), Key::Backspace, Key::Char('g')] {
+			assert!(view.handle_key(key, &[], 1));
+		}
+		assert_eq!(view.search, Some(String::from("pg")));
+		view.handle_key(Key::Enter, &[], 1);
+		assert_eq!(view.search, None);
+		assert_eq!(view.take_filter().map(|filter| filter.map(|regex| regex.as_str().to_owned())),
+			Some(Some(String::from("pg"))));
+		assert!(view.take_filter().is_none());
+
+		//an invalid pattern keeps the filter and shows an error
+		for key in vec![Key::Char('/'), Key::Char('('), Key::Enter] {
+			view.handle_key(key, &[], 1);
+		}
+		assert!(view.take_filter().is_none());
+		assert!(view.message.as_ref().unwrap().starts_with("error: invalid pattern"));
+
+		//an empty pattern clears it
+		view.handle_key(Key::Char('/'), &[], 1);
+		view.handle_key(Key::Enter, &[], 1);
+		assert!(view.take_filter().unwrap().is_none());
+	}
+
+	#[test]
+	fn stop_continue_and_terminate() {
+		let mut child = spawn_sleep();
+		let pid = child.id() as i32;
+		Action::Signal(libc::SIGSTOP).run(pid).unwrap();
+		assert_eq!(wait_for_state(pid, 'T'), 'T');
+		Action::Signal(libc::SIGCONT).run(pid).unwrap();
+		assert_eq!(wait_for_state(pid, 'S'), 'S');
+		Action::Signal(libc::SIGTERM).run(pid).unwrap();
+		assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+	}
+
+	#[test]
+	fn confirmation_checks_the_start_time() {
+		let mut child = spawn_sleep();
+		let pid = child.id() as i32;
+		let starttime = process::start_time(pid).unwrap();
+		let message = pending(pid, starttime + 1, Action::Signal(libc::SIGKILL)).confirm();
+		assert!(message.ends_with("process is gone"), "{}", message);
+		assert!(child.try_wait().unwrap().is_none());
+
+		let message = pending(pid, starttime, Action::Signal(libc::SIGKILL)).confirm();
+		assert!(message.starts_with("done"), "{}", message);
+		assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGKILL));
+	}
+
+	fn rows(count: i32) -> Vec<Row> {
+		(1..count + 1).map(|pid| Row {
+			pid: pid,
+			depth: 0,
+			name: format!("process {}", pid),
+			state: 'S',
+			nice: 0,
+			starttime: 0,
+			cpu_usage: 0.0,
+			rss: 0,
+			children: 0,
+			collapsed: false
+		}).collect()
+	}
+
+	#[test]
+	fn mouse_wheel_and_clicks() {
+		let rows = rows(10);
+		let mut view = ProcessView::new();
+		view.scroll_by(&rows, 4, true);
+		assert_eq!((view.scroll, view.selected), (3, Some(4)));
+		view.scroll_by(&rows, 4, true);
+		view.scroll_by(&rows, 4, true);
+		assert_eq!((view.scroll, view.selected), (6, Some(7)));
+		view.click(&rows, 9);
+		view.scroll_by(&rows, 4, false);
+		assert_eq!((view.scroll, view.selected), (3, Some(7)));
+		view.scroll_to_selection(&rows, 4);
+		assert_eq!(view.scroll, 3);
+		//rows below the end of the list are ignored
+		view.click(&rows, 12);
+		assert_eq!(view.selected, Some(7));
+	}
+
+	#[test]
+	fn keys_ask_before_sending() {
+		let mut child = spawn_sleep();
+		let pid = child.id() as i32;
+		let rows = vec![Row {
+			pid: pid,
+			depth: 0,
+			name: String::from("sleep"),
+			state: 'S',
+			nice: 0,
+			starttime: process::start_time(pid).unwrap(),
+			cpu_usage: 0.0,
+			rss: 0,
+			children: 0,
+			collapsed: false
+		}];
+		let mut view = ProcessView::new();
+		assert!(view.handle_key(Key::Char('t'), &rows, 1));
+		assert!(view.pending.is_some());
+		assert!(view.handle_key(Key::Char('x'), &rows, 1));
+		assert_eq!(view.message, Some(String::from("cancelled")));
+		assert!(child.try_wait().unwrap().is_none());
+
+		view.handle_key(Key::Char('t'), &rows, 1);
+		view.handle_key(Key::Char('y'), &rows, 1);
+		assert!(view.message.as_ref().unwrap().starts_with("done"));
+		assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+	}
+
+	#[test]
+	fn renice_of_init_is_not_permitted() {
+		//runs in a forked child, which drops root first
+		unsafe {
+			let child = libc::fork();
+			assert!(child >= 0);
+			if child == 0 {
+				if libc::geteuid() == 0 && libc::setuid(65534) != 0 {
+					libc::_exit(2);
+				}
+				let code = match Action::Renice(-1).run(1) {
+					Err(ref err) if err.raw_os_error() == Some(libc::EPERM) => 0,
+					_ => 1
+				};
+				libc::_exit(code);
+			}
+			let mut status = 0;
+			libc::waitpid(child, &mut status, 0);
+			assert!(libc::WIFEXITED(status));
+			assert_eq!(libc::WEXITSTATUS(status), 0);
+		}
+	}
+}
@@ -0,0 +1,392 @@
+/*
+Draws the screen modes on the alternate screen of the terminal without flicker
+The printers write a whole frame into a grid of cells, flush() compares it with the frame on screen
+and only sends the cells that changed. The terminal is restored when the screen is dropped
+Wide characters (like CJK) take two cells, the second one is covered by the first
+*/
+
+use term::{self, color, Attr};
+
+use theme::Color;
+use printutils::terminal_size;
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Stdout, Write};
+use std::mem;
+
+use unicode_width::UnicodeWidthChar;
+
+//alternate screen, hidden cursor
+const ENTER: &'static str = "\x1b[?1049h\x1b[?25l\x1b[2J";
+const LEAVE: &'static str = "\x1b[?1006l\x1b[?1000l\x1b[0m\x1b[?25h\x1b[?1049l";
+//clicks and the wheel are reported as SGR sequences, which work beyond column 223
+const MOUSE: &'static str = "\x1b[?1000h\x1b[?1006h";
+
+#[derive(Clone, PartialEq)]
+struct Cell {
+	ch: char, //COVERED for the right half of a wide character
+	style: String //the SGR sequences in effect, empty for the default colors
+}
+
+const COVERED: char = '\0';
+
+impl Cell {
+	fn blank() -> Cell {
+		Cell {
+			ch: ' ',
+			style: String::new()
+		}
+	}
+
+	fn width(&self) -> usize {
+		UnicodeWidthChar::width(self.ch).unwrap_or(1)
+	}
+}
+
+struct Frame {
+	width: usize,
+	height: usize,
+	cells: Vec<Cell>,
+	row: usize,
+	column: usize,
+	style: String
+}
+
+impl Frame {
+	fn new(width: usize, height: usize) -> Frame {
+		Frame {
+			width: width,
+			height: height,
+			cells: vec![Cell::blank(); width * height],
+			row: 0,
+			column: 0,
+			style: String::new()
+		}
+	}
+
+	//text as the printers write it: characters, newlines and SGR sequences, everything outside the grid is cut off
+	fn write(&mut self, text: &str) {
+		let mut chars = text.chars();
+		while let Some(ch) = chars.next() {
+			match ch {
+				'\x1b' => {
+					//ESC [ parameters, then a final byte between '@' and '~'. Only SGR (final 'm') is kept
+					match chars.next() {
+						Some('[') => {},
+						Some('(') | Some(')') => { chars.next(); continue; }, //character set
+						_ => continue
+					}
+					let mut parameters = String::new();
+					for next in chars.by_ref() {
+						if next >= '@' && next <= '~' {
+							if next == 'm' {
+								self.sgr(&parameters);
+							}
+							break;
+						}
+						parameters.push(next);
+					}
+				},
+				'\n' => {
+					self.row += 1;
+					self.column = 0;
+				},
+				'\r' => self.column = 0,
+				ch if ch < ' ' => {},
+				ch => {
+					//combining characters are dropped, they would not take a cell of their own
+					let width = UnicodeWidthChar::width(ch).unwrap_or(0);
+					if width == 0 {
+						continue;
+					}
+					if self.row < self.height && self.column < self.width {
+						//a wide character that does not fit into the last column is left out
+						let ch = if self.column + width > self.width { ' ' } else { ch };
+						self.put(self.column, Cell {
+							ch: ch,
+							style: self.style.clone()
+						});
+						if ch != ' ' && width == 2 {
+							self.put(self.column + 1, Cell {
+								ch: COVERED,
+								style: self.style.clone()
+							});
+						}
+					}
+					self.column += width;
+				}
+			}
+		}
+	}
+
+	//sets a cell of the current row, the other half of a wide character it overwrites is blanked
+	fn put(&mut self, column: usize, cell: Cell) {
+		let index = self.row * self.width + column;
+		if self.cells[index].ch == COVERED && cell.ch != COVERED && column > 0 {
+			self.cells[index - 1] = Cell::blank();
+		}
+		if self.cells[index].width() == 2 && column + 1 < self.width {
+			self.cells[index + 1] = Cell::blank();
+		}
+		self.cells[index] = cell;
+	}
+
+	fn sgr(&mut self, parameters: &str) {
+		if parameters.is_empty() || parameters == "0" {
+			self.style.clear();
+		}
+		else {
+			let _ = write!(self.style, "\x1b[{}m", parameters);
+		}
+	}
+
+	//the output that turns the shown frame into this one, everything is drawn again after a resize
+	fn diff(&self, shown: Option<&Frame>) -> String {
+		let mut output = String::new();
+		let cleared;
+		let shown = match shown {
+			Some(v) if v.width == self.width && v.height == self.height => v,
+			_ => {
+				output.push_str("\x1b[0m\x1b[2J");
+				cleared = Frame::new(self.width, self.height);
+				&cleared
+			}
+		};
+		let mut cursor = None;
+		let mut style = "";
+		for (index, cell) in self.cells.iter().enumerate() {
+			//the right half of a wide character is drawn with its left half
+			if shown.cells[index] == *cell || cell.ch == COVERED {
+				continue;
+			}
+			let (row, column) = (index / self.width, index % self.width);
+			if cursor != Some((row, column)) {
+				let _ = write!(output, "\x1b[{};{}H", row + 1, column + 1);
+			}
+			if cell.style != style {
+				output.push_str("\x1b[0m");
+				output.push_str(&cell.style);
+				style = &cell.style;
+			}
+			output.push(cell.ch);
+			//terminals do not agree on the width of every character, so the cursor is moved after wide ones
+			cursor = if cell.width() == 1 { Some((row, column + 1)) } else { None };
+		}
+		if !style.is_empty() {
+			output.push_str("\x1b[0m");
+		}
+		output
+	}
+}
+
+pub struct Screen {
+	out: Stdout,
+	shown: Option<Frame>, //None until the first frame is drawn
+	frame: Frame
+}
+
+impl Screen {
+	//mouse: report clicks and the wheel, only if they are read from stdin
+	pub fn new(mouse: bool) -> Screen {
+		let (width, height) = terminal_size();
+		let mut out = io::stdout();
+		let _ = out.write_all(ENTER.as_bytes());
+		if mouse {
+			let _ = out.write_all(MOUSE.as_bytes());
+		}
+		let _ = out.flush();
+		Screen {
+			out: out,
+			shown: None,
+			frame: Frame::new(width, height)
+		}
+	}
+}
+
+impl Write for Screen {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.frame.write(&String::from_utf8_lossy(buf));
+		Ok(buf.len())
+	}
+
+	//shows the frame and starts the next one, in the size the terminal has now
+	fn flush(&mut self) -> io::Result<()> {
+		let output = self.frame.diff(self.shown.as_ref());
+		try!(self.out.write_all(output.as_bytes()));
+		try!(self.out.flush());
+		let (width, height) = terminal_size();
+		self.shown = Some(mem::replace(&mut self.frame, Frame::new(width, height)));
+		Ok(())
+	}
+}
+
+impl term::Terminal for Screen {
+	type Output = Stdout;
+
+	fn fg(&mut self, color: color::Color) -> term::Result<()> {
+		self.frame.style.push_str(&Color::Basic(color).escape());
+		Ok(())
+	}
+
+	fn bg(&mut self, color: color::Color) -> term::Result<()> {
+		let code = if color < 8 { 40 + color } else { 100 + color - 8 };
+		self.frame.sgr(&format!("{}", code));
+		Ok(())
+	}
+
+	fn attr(&mut self, attr: Attr) -> term::Result<()> {
+		let code = match attr {
+			Attr::Bold => "1",
+			Attr::Dim => "2",
+			Attr::Italic(true) => "3",
+			Attr::Underline(true) => "4",
+			Attr::Blink => "5",
+			Attr::Standout(true) | Attr::Reverse => "7",
+			Attr::Secure => "8",
+			Attr::Italic(false) => "23",
+			Attr::Underline(false) => "24",
+			Attr::Standout(false) => "27",
+			Attr::ForegroundColor(color) => return self.fg(color),
+			Attr::BackgroundColor(color) => return self.bg(color)
+		};
+		self.frame.sgr(code);
+		Ok(())
+	}
+
+	fn supports_attr(&self, _: Attr) -> bool {
+		true
+	}
+
+	fn reset(&mut self) -> term::Result<()> {
+		self.frame.style.clear();
+		Ok(())
+	}
+
+	fn supports_reset(&self) -> bool {
+		true
+	}
+
+	fn supports_color(&self) -> bool {
+		true
+	}
+
+	fn cursor_up(&mut self) -> term::Result<()> {
+		self.frame.row = self.frame.row.saturating_sub(1);
+		Ok(())
+	}
+
+	//clears the rest of the line
+	fn delete_line(&mut self) -> term::Result<()> {
+		let frame = &mut self.frame;
+		if frame.row < frame.height {
+			for column in frame.column..frame.width {
+				frame.put(column, Cell::blank());
+			}
+		}
+		Ok(())
+	}
+
+	fn carriage_return(&mut self) -> term::Result<()> {
+		self.frame.column = 0;
+		Ok(())
+	}
+
+	fn get_ref(&self) -> &Stdout {
+		&self.out
+	}
+
+	fn get_mut(&mut self) -> &mut Stdout {
+		&mut self.out
+	}
+
+	fn into_inner(self) -> Stdout {
+		io::stdout() //the screen is restored on drop
+	}
+}
+
+impl Drop for Screen {
+	fn drop(&mut self) {
+		let _ = self.out.write_all(LEAVE.as_bytes());
+		let _ = self.out.flush();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	//the covered halves of wide characters are left out
+	fn text(frame: &Frame, row: usize) -> String {
+		frame.cells[row * frame.width..(row + 1) * frame.width].iter()
+			.filter(|cell| cell.ch != COVERED)
+			.map(|cell| cell.ch)
+			.collect()
+	}
+
+	#[test]
+	fn writes_into_the_grid() {
+		let mut frame = Frame::new(8, 3);
+		frame.write("CPU \x1b[36m\x1b[1m42\x1b[0m %\nRAM: a line that is too long\n\n\nbelow");
+		assert_eq!(text(&frame, 0), "CPU 42 %");
+		assert_eq!(text(&frame, 1), "RAM: a l");
+		assert_eq!(text(&frame, 2), "        ");
+		assert_eq!(frame.cells[4].style, "\x1b[36m\x1b[1m");
+		assert_eq!(frame.cells[6].style, "");
+		//other sequences are dropped
+		let mut frame = Frame::new(4, 1);
+		frame.write("a\x1b[2Kb\x1b(Bc");
+		assert_eq!(text(&frame, 0), "abc ");
+	}
+
+	#[test]
+	fn wide_characters_take_two_cells() {
+		let mut frame = Frame::new(8, 2);
+		frame.write("日本ab\nabc漢字e\u{301}");
+		assert_eq!(text(&frame, 0), "日本ab  ");
+		assert_eq!(frame.cells[1].ch, COVERED);
+		assert_eq!(frame.cells[4].ch, 'a');
+		//the combining accent is dropped, 字 still fits
+		assert_eq!(text(&frame, 1), "abc漢字e");
+		//a wide character does not fit into the last column
+		let mut frame = Frame::new(3, 1);
+		frame.write("ab日");
+		assert_eq!(text(&frame, 0), "ab ");
+		//overwriting one half of a wide character blanks the other one
+		let mut frame = Frame::new(4, 1);
+		frame.write("日本\rx");
+		frame.column = 3;
+		frame.write("y");
+		assert_eq!(text(&frame, 0), "x  y");
+	}
+
+	#[test]
+	fn cursor_is_moved_after_wide_characters() {
+		let mut frame = Frame::new(6, 1);
+		frame.write("日本ab");
+		assert_eq!(frame.diff(None), "\x1b[0m\x1b[2J\x1b[1;1H日\x1b[1;3H本\x1b[1;5Hab");
+		let mut changed = Frame::new(6, 1);
+		changed.write("日x ab");
+		assert_eq!(changed.diff(Some(&frame)), "\x1b[1;3Hx ");
+		assert_eq!(frame.diff(Some(&changed)), "\x1b[1;3H本");
+	}
+
+	#[test]
+	fn first_frame_is_drawn_whole() {
+		let mut frame = Frame::new(4, 2);
+		frame.write("ab\n c");
+		assert_eq!(frame.diff(None), "\x1b[0m\x1b[2J\x1b[1;1Hab\x1b[2;2Hc");
+	}
+
+	#[test]
+	fn only_changed_cells_are_sent() {
+		let mut shown = Frame::new(6, 2);
+		shown.write("12 %\n50 %");
+		let mut frame = Frame::new(6, 2);
+		frame.write("13 %\n\x1b[31m50\x1b[0m %");
+		assert_eq!(frame.diff(Some(&shown)), "\x1b[1;2H3\x1b[2;1H\x1b[0m\x1b[31m50\x1b[0m");
+		assert_eq!(frame.diff(Some(&frame)), "");
+		//a resized terminal is cleared and drawn again
+		let resized = Frame::new(7, 2);
+		assert!(frame.diff(Some(&resized)).starts_with("\x1b[0m\x1b[2J"));
+	}
+}
@@ -0,0 +1,206 @@
+/*
+Summarizes every sample taken during a run, printed on exit
+The samples are not kept, so the memory used stays the same no matter how long run-info runs
+*/
+
+use cpuinfo::CPUInfo;
+use meminfo::MemInfo;
+use printutils::calc_cpu_load_percentage;
+
+use time::{self, Timespec};
+
+pub struct Summary {
+	pub min: f64,
+	pub mean: f64,
+	pub max: f64,
+	pub p95: f64
+}
+
+//resolution of the percentiles, one bucket per 0.1 %
+const BUCKETS: usize = 1000;
+
+//running min/max/sum and a histogram of values from 0.0 to 1.0, just like the load percentages
+pub struct Accumulator {
+	count: u64,
+	sum: f64,
+	min: f64,
+	max: f64,
+	buckets: Vec<u64>
+}
+
+impl Accumulator {
+	pub fn new() -> Accumulator {
+		Accumulator {
+			count: 0,
+			sum: 0.0,
+			min: 0.0,
+			max: 0.0,
+			buckets: vec![0; BUCKETS]
+		}
+	}
+
+	pub fn push(&mut self, value: f64) {
+		let value = value.max(0.0).min(1.0);
+		if self.count == 0 {
+			self.min = value;
+			self.max = value;
+		}
+		self.min = self.min.min(value);
+		self.max = self.max.max(value);
+		self.count += 1;
+		self.sum += value;
+		let bucket = ((value * BUCKETS as f64) as usize).min(BUCKETS - 1);
+		self.buckets[bucket] += 1;
+	}
+
+	//nearest-rank percentile (p from 0.0 to 1.0), exact up to the bucket size
+	pub fn percentile(&self, p: f64) -> f64 {
+		if self.count == 0 {
+			return 0.0;
+		}
+		let rank = ((p * self.count as f64).ceil() as u64).max(1);
+		let mut seen = 0;
+		for (bucket, count) in self.buckets.iter().enumerate() {
+			seen += *count;
+			if seen >= rank {
+				//the upper end of the bucket, but never outside of the seen values
+				return ((bucket + 1) as f64 / BUCKETS as f64).max(self.min).min(self.max);
+			}
+		}
+		self.max
+	}
+
+	pub fn summary(&self) -> Summary {
+		if self.count == 0 {
+			return Summary {min: 0.0, mean: 0.0, max: 0.0, p95: 0.0};
+		}
+		Summary {
+			min: self.min,
+			mean: self.sum / self.count as f64,
+			max: self.max,
+			p95: self.percentile(0.95)
+		}
+	}
+}
+
+pub struct Session {
+	start: Timespec,
+	total: Accumulator,
+	cores: Vec<Accumulator>,
+	ram: Accumulator,
+	swap: Accumulator
+}
+
+impl Session {
+	pub fn new() -> Session {
+		Session {
+			start: time::get_time(),
+			total: Accumulator::new(),
+			cores: Vec::new(),
+			ram: Accumulator::new(),
+			swap: Accumulator::new()
+		}
+	}
+
+	//takes the cpu delta of the current tick
+	pub fn record(&mut self, cpu: &CPUInfo, mem: &MemInfo) {
+		//the very first delta spans no time at all and would only add zeros
+		if cpu.total_load.busy + cpu.total_load.idle == 0 {
+			return;
+		}
+		self.total.push(calc_cpu_load_percentage(&cpu.total_load));
+		while self.cores.len() < cpu.cores_load.len() {
+			self.cores.push(Accumulator::new());
+		}
+		for (core, core_load) in cpu.cores_load.iter().enumerate() {
+			self.cores[core].push(calc_cpu_load_percentage(core_load));
+		}
+		self.ram.push(mem.memory_use());
+		self.swap.push(mem.swap_use());
+	}
+
+	pub fn samples(&self) -> usize {
+		self.total.count as usize
+	}
+
+	pub fn duration_secs(&self) -> i64 {
+		(time::get_time() - self.start).num_seconds()
+	}
+
+	//(name, summary) pairs in display order: total, cores, ram, swap
+	pub fn summaries(&self) -> Vec<(String, Summary)> {
+		let mut result = Vec::new();
+		result.push((String::from("TOTAL"), self.total.summary()));
+		for (core, samples) in self.cores.iter().enumerate() {
+			result.push((format!("CPU {}", core + 1), samples.summary()));
+		}
+		result.push((String::from("RAM"), self.ram.summary()));
+		result.push((String::from("SWAP"), self.swap.summary()));
+		result
+	}
+
+	//hand-written, since the structure is flat and all values are numbers
+	pub fn to_json(&self) -> String {
+		let cores: Vec<String> = self.cores.iter()
+			.map(|samples| summary_json(&samples.summary()))
+			.collect();
+		format!("{{\"duration_secs\":{},\"samples\":{},\"cpu\":{{\"total\":{},\"cores\":[{}]}},\"ram\":{},\"swap\":{}}}",
+			self.duration_secs(),
+			self.samples(),
+			summary_json(&self.total.summary()),
+			cores.join(","),
+			summary_json(&self.ram.summary()),
+			summary_json(&self.swap.summary()))
+	}
+}
+
+//values are written as percentages
+fn summary_json(summary: &Summary) -> String {
+	format!("{{\"min\":{:.2},\"mean\":{:.2},\"max\":{:.2},\"p95\":{:.2}}}",
+		summary.min * 100.0, summary.mean * 100.0, summary.max * 100.0, summary.p95 * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn empty_summary_is_zero() {
+		let summary = Accumulator::new().summary();
+		assert_eq!((summary.min, summary.mean, summary.max, summary.p95), (0.0, 0.0, 0.0, 0.0));
+	}
+
+	#[test]
+	fn summary_of_samples() {
+		let mut accumulator = Accumulator::new();
+		for value in 1..101 {
+			accumulator.push(value as f64 / 100.0);
+		}
+		let summary = accumulator.summary();
+		assert_eq!(summary.min, 0.01);
+		assert_eq!(summary.max, 1.0);
+		assert!((summary.mean - 0.505).abs() < 1e-9);
+		//the exact nearest-rank p95 is 0.95, the histogram may be one bucket off
+		assert!((summary.p95 - 0.95).abs() <= 1.0 / BUCKETS as f64 + 1e-9, "{}", summary.p95);
+	}
+
+	#[test]
+	fn percentile_stays_within_the_samples() {
+		let mut accumulator = Accumulator::new();
+		for _ in 0..10 {
+			accumulator.push(0.4321);
+		}
+		assert_eq!(accumulator.percentile(0.95), 0.4321);
+		assert_eq!(accumulator.percentile(0.0), 0.4321);
+	}
+
+	#[test]
+	fn values_are_clamped() {
+		let mut accumulator = Accumulator::new();
+		accumulator.push(-0.5);
+		accumulator.push(1.5);
+		let summary = accumulator.summary();
+		assert_eq!((summary.min, summary.max), (0.0, 1.0));
+		assert_eq!(accumulator.buckets.iter().sum::<u64>(), 2);
+	}
+}
@@ -0,0 +1,58 @@
+/*
+Catches SIGINT/SIGTERM so the main loop can shut down cleanly, and SIGHUP to reopen the output file
+On a terminal SIGHUP means that it was closed, which ends the program like SIGTERM
+*/
+
+use libc;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+//the sleep is split into slices of this length to react quickly to a signal
+const SLEEP_SLICE: u64 = 50;
+
+extern "C" fn handle_terminate(_: libc::c_int) {
+	TERMINATE.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_reload(_: libc::c_int) {
+	RELOAD.store(true, Ordering::SeqCst);
+}
+
+//reload: SIGHUP reopens the output file instead of terminating
+pub fn install(reload: bool) {
+	let hangup = if reload { handle_reload } else { handle_terminate };
+	unsafe {
+		libc::signal(libc::SIGINT, handle_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t);
+		libc::signal(libc::SIGTERM, handle_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t);
+		libc::signal(libc::SIGHUP, hangup as extern "C" fn(libc::c_int) as libc::sighandler_t);
+	}
+}
+
+//used to quit from the keyboard
+pub fn request_terminate() {
+	TERMINATE.store(true, Ordering::SeqCst);
+}
+
+pub fn terminate_requested() -> bool {
+	TERMINATE.load(Ordering::SeqCst)
+}
+
+//true once per SIGHUP
+pub fn reload_requested() -> bool {
+	RELOAD.swap(false, Ordering::SeqCst)
+}
+
+//sleeps for the given time (in ms), but returns early if the program should terminate
+pub fn sleep(delay: u64) {
+	let mut remaining = delay;
+	while remaining > 0 && !terminate_requested() {
+		let slice = if remaining < SLEEP_SLICE { remaining } else { SLEEP_SLICE };
+		thread::sleep(Duration::from_millis(slice));
+		remaining -= slice;
+	}
+}
@@ -0,0 +1,268 @@
+/*
+Color themes (--theme), with 256 colors and 24-bit colors if the terminal supports them
+Colors the terminal can not show are replaced by the closest one it can show
+*/
+
+use term::color;
+
+use std::env;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Color {
+	Basic(u32), //the 16 colors of term::color
+	Indexed(u8), //xterm 256 color palette
+	Rgb(u8, u8, u8)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Depth {
+	Basic,
+	Indexed,
+	TrueColor
+}
+
+impl Depth {
+	//"16", "256" or "truecolor"
+	pub fn from_str(name: &str) -> Option<Depth> {
+		match name {
+			"16" => Some(Depth::Basic),
+			"256" => Some(Depth::Indexed),
+			"truecolor" => Some(Depth::TrueColor),
+			_ => None
+		}
+	}
+
+	//COLORTERM is set by terminals with 24-bit colors, TERM names the 256 color ones
+	pub fn detect() -> Depth {
+		let colorterm = env::var("COLORTERM").unwrap_or(String::new());
+		let term = env::var("TERM").unwrap_or(String::new());
+		if colorterm == "truecolor" || colorterm == "24bit" {
+			Depth::TrueColor
+		}
+		else if term.contains("256color") {
+			Depth::Indexed
+		}
+		else {
+			Depth::Basic
+		}
+	}
+}
+
+//the RGB values xterm uses for the 16 basic colors
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+	(0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+	(127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255)
+];
+
+//the levels of the 6x6x6 color cube of the 256 color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+	//"#rrggbb", a palette index from 0 to 255 or a name like "cyan" or "bright-red"
+	pub fn from_str(value: &str) -> Option<Color> {
+		if value.starts_with('#') && value.len() == 7 {
+			let channel = |index: usize| u8::from_str_radix(&value[index..index + 2], 16).ok();
+			return match (channel(1), channel(3), channel(5)) {
+				(Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+				_ => None
+			};
+		}
+		if let Ok(index) = value.parse::<u8>() {
+			return Some(Color::Indexed(index));
+		}
+		let (bright, name) = if value.starts_with("bright-") { (8, &value[7..]) } else { (0, value) };
+		let basic = match name {
+			"black" => color::BLACK,
+			"red" => color::RED,
+			"green" => color::GREEN,
+			"yellow" => color::YELLOW,
+			"blue" => color::BLUE,
+			"magenta" => color::MAGENTA,
+			"cyan" => color::CYAN,
+			"white" => color::WHITE,
+			_ => return None
+		};
+		Some(Color::Basic(basic + bright))
+	}
+
+	fn rgb(&self) -> (u8, u8, u8) {
+		match *self {
+			Color::Basic(code) => BASIC_RGB[(code as usize).min(15)],
+			Color::Indexed(index) if index < 16 => BASIC_RGB[index as usize],
+			Color::Indexed(index) if index < 232 => {
+				let index = index as usize - 16;
+				(CUBE_LEVELS[index / 36], CUBE_LEVELS[index / 6 % 6], CUBE_LEVELS[index % 6])
+			},
+			Color::Indexed(index) => {
+				let gray = 8 + (index - 232) * 10;
+				(gray, gray, gray)
+			},
+			Color::Rgb(r, g, b) => (r, g, b)
+		}
+	}
+
+	//the closest color the terminal can show
+	pub fn downgrade(&self, depth: Depth) -> Color {
+		match (*self, depth) {
+			(Color::Basic(_), _) | (_, Depth::TrueColor) | (Color::Indexed(_), Depth::Indexed) => *self,
+			(Color::Rgb(r, g, b), Depth::Indexed) => Color::Indexed(nearest_indexed(r, g, b)),
+			(_, Depth::Basic) => {
+				let (r, g, b) = self.rgb();
+				let nearest = (0..16).min_by_key(|code| distance(BASIC_RGB[*code], (r, g, b))).unwrap();
+				Color::Basic(nearest as u32)
+			}
+		}
+	}
+
+	//the SGR sequence that sets this color as foreground
+	pub fn escape(&self) -> String {
+		match *self {
+			Color::Basic(code) if code < 8 => format!("\x1b[{}m", 30 + code),
+			Color::Basic(code) => format!("\x1b[{}m", 90 + code - 8),
+			Color::Indexed(index) => format!("\x1b[38;5;{}m", index),
+			Color::Rgb(r, g, b) => format!("\x1b[38;2;{};{};{}m", r, g, b)
+		}
+	}
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+	let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+	channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+//the closer one of the color cube entry and the gray ramp entry
+fn nearest_indexed(r: u8, g: u8, b: u8) -> u8 {
+	let level = |value: u8| (0..6).min_by_key(|level| (CUBE_LEVELS[*level] as i32 - value as i32).abs()).unwrap();
+	let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+	let average = (r as u32 + g as u32 + b as u32) / 3;
+	let gray = 232 + ((average.max(8) - 8) / 10).min(23) as usize;
+	let candidates = [cube as u8, gray as u8];
+	*candidates.iter().min_by_key(|index| distance(Color::Indexed(**index).rgb(), (r, g, b))).unwrap()
+}
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+	pub highlight: Color, //values and labels
+	pub header: Color,
+	pub graph: Color,
+	//the bars are drawn in these three
+	pub red: Color,
+	pub green: Color,
+	pub yellow: Color
+}
+
+impl Theme {
+	pub fn from_str(name: &str) -> Option<Theme> {
+		match name {
+			"dark" | "custom" => Some(Theme {
+				highlight: Color::Basic(color::CYAN),
+				header: Color::Basic(color::YELLOW),
+				graph: Color::Basic(color::CYAN),
+				red: Color::Basic(color::RED),
+				green: Color::Basic(color::GREEN),
+				yellow: Color::Basic(color::YELLOW)
+			}),
+			//cyan and yellow are hard to read on white
+			"light" => Some(Theme {
+				highlight: Color::Basic(color::BLUE),
+				header: Color::Basic(color::MAGENTA),
+				graph: Color::Basic(color::BLUE),
+				red: Color::Basic(color::RED),
+				green: Color::Indexed(28),
+				yellow: Color::Indexed(130)
+			}),
+			"solarized" => Some(Theme {
+				highlight: Color::Rgb(0x2a, 0xa1, 0x98),
+				header: Color::Rgb(0xb5, 0x89, 0x00),
+				graph: Color::Rgb(0x26, 0x8b, 0xd2),
+				red: Color::Rgb(0xdc, 0x32, 0x2f),
+				green: Color::Rgb(0x85, 0x99, 0x00),
+				yellow: Color::Rgb(0xcb, 0x4b, 0x16)
+			}),
+			"high-contrast" => Some(Theme {
+				highlight: Color::Basic(color::BRIGHT_WHITE),
+				header: Color::Basic(color::BRIGHT_YELLOW),
+				graph: Color::Basic(color::BRIGHT_WHITE),
+				red: Color::Basic(color::BRIGHT_RED),
+				green: Color::Basic(color::BRIGHT_GREEN),
+				yellow: Color::Basic(color::BRIGHT_YELLOW)
+			}),
+			_ => None
+		}
+	}
+
+	//the printers ask for the basic colors of the bars
+	pub fn bar(&self, color_code: u32) -> Color {
+		match color_code {
+			color::RED => self.red,
+			color::GREEN => self.green,
+			color::YELLOW => self.yellow,
+			color::CYAN => self.highlight,
+			_ => Color::Basic(color_code)
+		}
+	}
+
+	//for the [colors] table of the config file
+	pub fn set(&mut self, role: &str, color: Color) -> bool {
+		match role {
+			"highlight" => self.highlight = color,
+			"header" => self.header = color,
+			"graph" => self.graph = color,
+			"red" => self.red = color,
+			"green" => self.green = color,
+			"yellow" => self.yellow = color,
+			_ => return false
+		}
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn color_values() {
+		assert_eq!(Color::from_str("#268bd2"), Some(Color::Rgb(0x26, 0x8b, 0xd2)));
+		assert_eq!(Color::from_str("136"), Some(Color::Indexed(136)));
+		assert_eq!(Color::from_str("cyan"), Some(Color::Basic(color::CYAN)));
+		assert_eq!(Color::from_str("bright-red"), Some(Color::Basic(color::BRIGHT_RED)));
+		for value in &["", "#12345", "#gg0000", "256", "purple", "bright-"] {
+			assert_eq!(Color::from_str(value), None, "{}", value);
+		}
+	}
+
+	#[test]
+	fn downgrade_to_the_palette() {
+		let blue = Color::Rgb(0x26, 0x8b, 0xd2);
+		assert_eq!(blue.downgrade(Depth::TrueColor), blue);
+		assert_eq!(blue.downgrade(Depth::Indexed), Color::Indexed(32));
+		assert_eq!(Color::Rgb(128, 128, 128).downgrade(Depth::Indexed), Color::Indexed(244));
+		assert_eq!(Color::Rgb(255, 0, 0).downgrade(Depth::Indexed), Color::Indexed(196));
+		assert_eq!(blue.downgrade(Depth::Basic), Color::Basic(color::CYAN));
+		assert_eq!(Color::Indexed(196).downgrade(Depth::Basic), Color::Basic(color::BRIGHT_RED));
+		assert_eq!(Color::Basic(color::CYAN).downgrade(Depth::Basic), Color::Basic(color::CYAN));
+	}
+
+	#[test]
+	fn escapes() {
+		assert_eq!(Color::Basic(color::CYAN).escape(), "\x1b[36m");
+		assert_eq!(Color::Basic(color::BRIGHT_RED).escape(), "\x1b[91m");
+		assert_eq!(Color::Indexed(130).escape(), "\x1b[38;5;130m");
+		assert_eq!(Color::Rgb(1, 2, 3).escape(), "\x1b[38;2;1;2;3m");
+	}
+
+	#[test]
+	fn themes() {
+		let dark = Theme::from_str("dark").unwrap();
+		assert_eq!(dark.bar(color::RED), Color::Basic(color::RED));
+		assert_eq!(dark.header, Color::Basic(color::YELLOW));
+		let mut light = Theme::from_str("light").unwrap();
+		assert_eq!(light.bar(color::YELLOW), Color::Indexed(130));
+		assert!(light.set("header", Color::Indexed(1)));
+		assert!(!light.set("background", Color::Indexed(1)));
+		assert_eq!(light.header, Color::Indexed(1));
+		assert!(Theme::from_str("solarized").is_some() && Theme::from_str("high-contrast").is_some());
+		assert!(Theme::from_str("neon").is_none());
+	}
+}
@@ -0,0 +1,120 @@
+/*
+Timestamps of the log line and the --format output (--timestamp, --utc)
+*/
+
+use time::{self, Timespec, Tm};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timestamp {
+	Iso8601, //2017-03-01T14:05:09+01:00, like date --iso-8601=seconds
+	Rfc3339, //2017-03-01 14:05:09.123+01:00, with milliseconds
+	Epoch,
+	EpochMs,
+	None
+}
+
+impl Timestamp {
+	pub fn from_str(name: &str) -> Option<Timestamp> {
+		match name {
+			"iso8601" => Some(Timestamp::Iso8601),
+			"rfc3339" => Some(Timestamp::Rfc3339),
+			"epoch" => Some(Timestamp::Epoch),
+			"epoch-ms" => Some(Timestamp::EpochMs),
+			"none" => Some(Timestamp::None),
+			_ => None
+		}
+	}
+
+	//None if no timestamp should be written
+	pub fn format(&self, now: Timespec, utc: bool) -> Option<String> {
+		let tm = if utc { time::at_utc(now) } else { time::at(now) };
+		match *self {
+			Timestamp::Iso8601 => Some(format!("{}T{}{}", date(&tm), clock(&tm), offset(&tm, utc))),
+			Timestamp::Rfc3339 => Some(format!("{} {}.{:03}{}", date(&tm), clock(&tm), now.nsec / 1000000, offset(&tm, false))),
+			Timestamp::Epoch => Some(format!("{}", now.sec)),
+			Timestamp::EpochMs => Some(format!("{}", now.sec * 1000 + now.nsec as i64 / 1000000)),
+			Timestamp::None => None
+		}
+	}
+
+	//the line protocol is read as ns by default, so seconds or ms would land in 1970
+	pub fn influx(&self, now: Timespec) -> Option<u64> {
+		match *self {
+			Timestamp::None => None,
+			_ => Some(now.sec as u64 * 1000000000 + now.nsec as u64)
+		}
+	}
+}
+
+fn date(tm: &Tm) -> String {
+	format!("{:04}-{:02}-{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday)
+}
+
+fn clock(tm: &Tm) -> String {
+	format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+//"+01:00", or "Z" for zulu time
+fn offset(tm: &Tm, zulu: bool) -> String {
+	if zulu && tm.tm_utcoff == 0 {
+		return String::from("Z");
+	}
+	let sign = if tm.tm_utcoff < 0 { '-' } else { '+' };
+	let minutes = tm.tm_utcoff.abs() / 60;
+	format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	//2017-03-01 14:05:09.042 UTC
+	const NOW: Timespec = Timespec { sec: 1488377109, nsec: 42000000 };
+
+	#[test]
+	fn utc_formats() {
+		assert_eq!(Timestamp::Iso8601.format(NOW, true).unwrap(), "2017-03-01T14:05:09Z");
+		assert_eq!(Timestamp::Rfc3339.format(NOW, true).unwrap(), "2017-03-01 14:05:09.042+00:00");
+		assert_eq!(Timestamp::Epoch.format(NOW, true).unwrap(), "1488377109");
+		assert_eq!(Timestamp::EpochMs.format(NOW, true).unwrap(), "1488377109042");
+		assert!(Timestamp::None.format(NOW, true).is_none());
+	}
+
+	#[test]
+	fn local_time_has_an_offset() {
+		let tm = time::at(NOW);
+		let local = Timestamp::Iso8601.format(NOW, false).unwrap();
+		assert!(local.starts_with(&format!("{}T{}", date(&tm), clock(&tm))));
+		assert!(local.ends_with(&offset(&tm, false)), "{}", local);
+		//the epoch is the same everywhere
+		assert_eq!(Timestamp::Epoch.format(NOW, false).unwrap(), "1488377109");
+	}
+
+	#[test]
+	fn offsets() {
+		let mut tm = time::at_utc(NOW);
+		assert_eq!(offset(&tm, true), "Z");
+		assert_eq!(offset(&tm, false), "+00:00");
+		tm.tm_utcoff = 5 * 3600 + 30 * 60;
+		assert_eq!(offset(&tm, false), "+05:30");
+		tm.tm_utcoff = -3 * 3600;
+		assert_eq!(offset(&tm, true), "-03:00");
+	}
+
+	#[test]
+	fn influx_precision() {
+		assert_eq!(Timestamp::Iso8601.influx(NOW), Some(1488377109042000000));
+		//InfluxDB and Telegraf take nanoseconds, whatever the log lines use
+		assert_eq!(Timestamp::Epoch.influx(NOW), Some(1488377109042000000));
+		assert_eq!(Timestamp::EpochMs.influx(NOW), Some(1488377109042000000));
+		assert_eq!(Timestamp::None.influx(NOW), None);
+	}
+
+	#[test]
+	fn timestamps_sort_as_text() {
+		let earlier = Timespec { sec: NOW.sec - 3600 * 24 * 40 + 1, nsec: 0 };
+		for format in &[Timestamp::Iso8601, Timestamp::Rfc3339] {
+			assert!(format.format(earlier, true).unwrap() < format.format(NOW, true).unwrap());
+		}
+	}
+}
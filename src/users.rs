@@ -0,0 +1,43 @@
+/*
+Resolves user ids to names through /etc/passwd
+*/
+
+use std::collections::HashMap;
+
+use fsutils::read_file;
+
+const PASSWD: &'static str = "/etc/passwd";
+
+pub struct UserNames {
+	names: HashMap<u32, String>
+}
+
+impl UserNames {
+	//users managed elsewhere (LDAP, ...) are missing, they are shown by their uid
+	pub fn load() -> UserNames {
+		UserNames::from_passwd(&read_file(PASSWD).unwrap_or(String::new()))
+	}
+
+	pub fn from_passwd(plain: &str) -> UserNames {
+		let mut names = HashMap::new();
+		//lines look like "name:password:uid:gid:comment:home:shell"
+		for line in plain.lines() {
+			let columns: Vec<&str> = line.split(':').collect();
+			if columns.len() >= 3 {
+				if let Ok(uid) = columns[2].parse::<u32>() {
+					names.entry(uid).or_insert(columns[0].to_owned());
+				}
+			}
+		}
+		UserNames {
+			names: names
+		}
+	}
+
+	pub fn name(&self, uid: u32) -> String {
+		match self.names.get(&uid) {
+			Some(name) => name.clone(),
+			None => format!("{}", uid)
+		}
+	}
+}
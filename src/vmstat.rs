@@ -0,0 +1,118 @@
+/*
+Reads /proc/vmstat to get the rates of page faults, swapping and page reclaim
+The file only holds counters since boot, so the rates are calculated from the last two datasets
+*/
+
+use std::collections::HashMap;
+use std::io::Result;
+
+use time;
+
+use fsutils::{read_keyed, counter_delta};
+
+pub struct VmStat {
+	//all in events per second since the last update
+	pub minor_faults: f64,
+	pub major_faults: f64,
+	pub swap_in: f64, //pages
+	pub swap_out: f64, //pages
+	pub scanned: f64, //pages scanned for reclaim by kswapd and direct reclaim
+	pub oom_kills: f64,
+
+	last_counters: HashMap<String, u64>,
+	last_update_ns: u64
+}
+
+impl VmStat {
+	pub fn new() -> VmStat {
+		VmStat {
+			minor_faults: 0.0,
+			major_faults: 0.0,
+			swap_in: 0.0,
+			swap_out: 0.0,
+			scanned: 0.0,
+			oom_kills: 0.0,
+			last_counters: HashMap::new(),
+			last_update_ns: 0
+		}
+	}
+
+	pub fn update(&mut self) -> Result<()> {
+		let counters = try!(read_keyed("/proc/vmstat"));
+		self.record(counters, time::precise_time_ns());
+		Ok(())
+	}
+
+	//the rates between the last counters and these
+	fn record(&mut self, counters: HashMap<String, u64>, now_ns: u64) {
+		if !self.last_counters.is_empty() {
+			let elapsed = now_ns.saturating_sub(self.last_update_ns) as f64 / 1000000000.0;
+			let old = &self.last_counters;
+			let rate = |delta: u64| if elapsed > 0.0 { delta as f64 / elapsed } else { 0.0 };
+
+			let faults = counter_delta(&counters, old, "pgfault");
+			let major_faults = counter_delta(&counters, old, "pgmajfault");
+			self.minor_faults = rate(faults.saturating_sub(major_faults));
+			self.major_faults = rate(major_faults);
+			self.swap_in = rate(counter_delta(&counters, old, "pswpin"));
+			self.swap_out = rate(counter_delta(&counters, old, "pswpout"));
+			//older kernels count per zone (pgscan_kswapd_normal, ...), pgscan_anon/file split the same scans by type
+			let scanned = counters.keys()
+				.filter(|key| key.starts_with("pgscan_kswapd") || key.starts_with("pgscan_direct")
+					|| key.starts_with("pgscan_khugepaged"))
+				.filter(|key| key.as_str() != "pgscan_direct_throttle")
+				.map(|key| counter_delta(&counters, old, key))
+				.sum();
+			self.scanned = rate(scanned);
+			self.oom_kills = rate(counter_delta(&counters, old, "oom_kill"));
+		}
+
+		self.last_counters = counters;
+		self.last_update_ns = now_ns;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn counters(pairs: &[(&str, u64)]) -> HashMap<String, u64> {
+		pairs.iter().map(|&(key, value)| (key.to_owned(), value)).collect()
+	}
+
+	#[test]
+	fn rates_over_the_elapsed_time() {
+		let mut vmstat = VmStat::new();
+		vmstat.record(counters(&[("pgfault", 1000), ("pgmajfault", 10), ("pswpin", 0), ("pswpout", 4)]), 1000000000);
+		//nothing to compare with yet
+		assert_eq!(vmstat.minor_faults, 0.0);
+		vmstat.record(counters(&[("pgfault", 2000), ("pgmajfault", 30), ("pswpin", 8), ("pswpout", 4), ("oom_kill", 1)]),
+			3000000000);
+		//over two seconds, the major faults are part of pgfault
+		assert_eq!(vmstat.minor_faults, 490.0);
+		assert_eq!(vmstat.major_faults, 10.0);
+		assert_eq!((vmstat.swap_in, vmstat.swap_out), (4.0, 0.0));
+		assert_eq!(vmstat.oom_kills, 0.5);
+		//no time passed
+		vmstat.record(counters(&[("pgfault", 3000)]), 3000000000);
+		assert_eq!(vmstat.minor_faults, 0.0);
+	}
+
+	#[test]
+	fn scans_are_summed_up() {
+		let mut vmstat = VmStat::new();
+		let old = counters(&[("pgscan_kswapd", 100), ("pgscan_direct", 50), ("pgscan_khugepaged", 0),
+			("pgscan_direct_throttle", 0), ("pgscan_anon", 150), ("pgscan_file", 0)]);
+		vmstat.record(old, 0);
+		vmstat.record(counters(&[("pgscan_kswapd", 400), ("pgscan_direct", 150), ("pgscan_khugepaged", 20),
+			("pgscan_direct_throttle", 7), ("pgscan_anon", 570), ("pgscan_file", 0)]), 1000000000);
+		//the anon/file split and the throttle events are not counted again
+		assert_eq!(vmstat.scanned, 420.0);
+		//older kernels count per zone
+		let mut vmstat = VmStat::new();
+		vmstat.record(counters(&[("pgscan_kswapd_normal", 0), ("pgscan_kswapd_dma32", 0), ("pgscan_direct_normal", 0)]), 0);
+		vmstat.record(counters(&[("pgscan_kswapd_normal", 30), ("pgscan_kswapd_dma32", 10), ("pgscan_direct_normal", 20)]),
+			2000000000);
+		assert_eq!(vmstat.scanned, 30.0);
+	}
+}
@@ -0,0 +1,509 @@
+/*
+Widgets and the layout that places them, the screen modes are declared as rows and columns of widgets
+A widget renders itself into lines of styled text for a given width, the layout cuts, pads and joins them
+*/
+
+use term::{self, color, Attr};
+
+use Settings;
+
+use printutils::{self, Thresholds, DEFAULT_THRESHOLDS, gradient_color, pad_string, pad_left, terminal_size};
+use history::{TieredGraph, Zoom};
+use graph::GRAPH_WIDTH;
+
+use std::io::Stdout;
+
+use unicode_width::UnicodeWidthStr;
+
+//rows of the history graphs, unless the config file sets another height
+pub const GRAPH_HEIGHT: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Style {
+	Plain,
+	Highlight, //values and labels
+	Header,
+	Graph,
+	Bar(u32) //one of the basic colors of the bars, as the theme draws it
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Span {
+	pub text: String,
+	pub style: Style
+}
+
+pub type Line = Vec<Span>;
+
+pub trait Widget {
+	//the lines of the widget, the layout cuts off what is wider than `width`
+	fn render(&self, width: usize) -> Vec<Line>;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Constraint {
+	Length(usize),
+	Percentage(usize), //of the width of the parent
+	Fill //shares what the others leave
+}
+
+pub enum Layout<'a> {
+	Widget(Box<Widget + 'a>),
+	Rows(Vec<Layout<'a>>),
+	Columns(Vec<(Constraint, Layout<'a>)>)
+}
+
+impl<'a, W: Widget + 'a> From<W> for Layout<'a> {
+	fn from(widget: W) -> Layout<'a> {
+		Layout::Widget(Box::new(widget))
+	}
+}
+
+impl<'a> Layout<'a> {
+	pub fn render(&self, width: usize) -> Vec<Line> {
+		match *self {
+			Layout::Widget(ref widget) => widget.render(width).into_iter().map(|line| cut(line, width)).collect(),
+			Layout::Rows(ref rows) => rows.iter().flat_map(|row| row.render(width)).collect(),
+			Layout::Columns(ref columns) => {
+				let constraints: Vec<Constraint> = columns.iter().map(|column| column.0).collect();
+				let widths = split(width, &constraints);
+				let rendered: Vec<Vec<Line>> = columns.iter().zip(widths.iter())
+					.map(|(column, width)| column.1.render(*width))
+					.collect();
+				let height = rendered.iter().map(|lines| lines.len()).max().unwrap_or(0);
+				let mut lines = Vec::new();
+				for y in 0..height {
+					let mut line = Vec::new();
+					for (index, column) in rendered.iter().enumerate() {
+						let part = column.get(y).cloned().unwrap_or(Vec::new());
+						//the last column is not padded, so no line ends with blanks
+						if index + 1 < rendered.len() {
+							line.extend(pad(part, widths[index]));
+						}
+						else {
+							line.extend(part);
+						}
+					}
+					lines.push(line);
+				}
+				lines
+			}
+		}
+	}
+}
+
+//the widths of the columns: lengths and percentages first (as long as there is space), the rest is shared by the fills
+pub fn split(width: usize, constraints: &[Constraint]) -> Vec<usize> {
+	let mut remaining = width;
+	let mut widths: Vec<usize> = constraints.iter().map(|constraint| {
+		let wanted = match *constraint {
+			Constraint::Length(v) => v,
+			Constraint::Percentage(v) => width * v / 100,
+			Constraint::Fill => 0
+		};
+		let given = wanted.min(remaining);
+		remaining -= given;
+		given
+	}).collect();
+	let fills = constraints.iter().filter(|constraint| **constraint == Constraint::Fill).count();
+	let mut extra = if fills > 0 { remaining % fills } else { 0 };
+	for (index, constraint) in constraints.iter().enumerate() {
+		if *constraint == Constraint::Fill {
+			widths[index] = remaining / fills;
+			if extra > 0 {
+				widths[index] += 1;
+				extra -= 1;
+			}
+		}
+	}
+	widths
+}
+
+//in columns of the terminal
+fn width_of(line: &Line) -> usize {
+	line.iter().map(|span| UnicodeWidthStr::width(span.text.as_str())).sum()
+}
+
+fn cut(line: Line, width: usize) -> Line {
+	let mut remaining = width;
+	let mut result = Vec::new();
+	for span in line {
+		if remaining == 0 {
+			break;
+		}
+		let length = UnicodeWidthStr::width(span.text.as_str());
+		if length <= remaining {
+			remaining -= length;
+			result.push(span);
+		}
+		else {
+			result.push(Span {
+				text: printutils::truncate_chars(span.text, remaining),
+				style: span.style
+			});
+			remaining = 0;
+		}
+	}
+	result
+}
+
+fn pad(mut line: Line, width: usize) -> Line {
+	let length = width_of(&line);
+	if length < width {
+		line.push(Span {
+			text: pad_string(String::new(), width - length),
+			style: Style::Plain
+		});
+	}
+	line
+}
+
+//draws the layout in the width of the terminal
+pub fn draw(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings, layout: &Layout) {
+	let (width, _) = terminal_size();
+	for line in layout.render(width) {
+		write_spans(term, settings, &line);
+		let _ = writeln!(term, "");
+	}
+}
+
+pub fn write_spans(term: &mut Box<term::Terminal<Output=Stdout> + Send>, settings: &Settings, line: &Line) {
+	for span in line {
+		match span.style {
+			Style::Plain => {
+				let _ = write!(term, "{}", span.text);
+				continue;
+			},
+			Style::Highlight => printutils::paint(term, settings, settings.theme.highlight),
+			Style::Header => printutils::paint(term, settings, settings.theme.header),
+			Style::Graph => printutils::paint(term, settings, settings.theme.graph),
+			Style::Bar(code) => printutils::colorize(term, settings, code)
+		}
+		printutils::attribute(term, settings, Attr::Bold);
+		let _ = write!(term, "{}", span.text);
+		printutils::reset(term, settings);
+	}
+}
+
+//one line of plain and highlighted text
+pub struct Text {
+	line: Line
+}
+
+impl Text {
+	pub fn new() -> Text {
+		Text {
+			line: Vec::new()
+		}
+	}
+
+	pub fn plain<S: Into<String>>(self, text: S) -> Text {
+		self.span(text.into(), Style::Plain)
+	}
+
+	pub fn highlight<S: Into<String>>(self, text: S) -> Text {
+		self.span(text.into(), Style::Highlight)
+	}
+
+	fn span(mut self, text: String, style: Style) -> Text {
+		self.line.push(Span {
+			text: text,
+			style: style
+		});
+		self
+	}
+
+	pub fn width(&self) -> usize {
+		width_of(&self.line)
+	}
+}
+
+impl Widget for Text {
+	fn render(&self, _: usize) -> Vec<Line> {
+		vec![self.line.clone()]
+	}
+}
+
+//the title in the middle of the width ( -> ===== CPU ===== )
+pub struct Header {
+	title: String
+}
+
+impl Header {
+	pub fn new<S: Into<String>>(title: S) -> Header {
+		Header {
+			title: title.into()
+		}
+	}
+}
+
+impl Widget for Header {
+	fn render(&self, width: usize) -> Vec<Line> {
+		let title = format!(" {} ", self.title);
+		let length = UnicodeWidthStr::width(title.as_str());
+		let half = width.saturating_sub(length) / 2;
+		let extend_first = half * 2 + length < width; //catch rounding errors
+		let first = if extend_first { half + 1 } else { half };
+		vec![vec![
+			Span { text: "=".repeat(first), style: Style::Plain },
+			Span { text: title, style: Style::Header },
+			Span { text: "=".repeat(half), style: Style::Plain }
+		]]
+	}
+}
+
+//a progress bar ( -> [======      ] ) where value ranges from 0.0 to 1.0 (panic otherwise)
+pub struct Bar {
+	value: f64,
+	color: u32,
+	thresholds: Option<Thresholds>, //yellow/red above these
+	gradient: bool //each segment gets the color of the load it stands for ( -> [=====GGGGGYYYRR   ] )
+}
+
+impl Bar {
+	//in one color, like the charge of a battery
+	pub fn new(value: f64, color: u32) -> Bar {
+		Bar {
+			value: value,
+			color: color,
+			thresholds: None,
+			gradient: false
+		}
+	}
+
+	//of a load (CPU, RAM, swap ...), colored by the thresholds or as gradient
+	pub fn load(settings: &Settings, value: f64, color: u32) -> Bar {
+		Bar {
+			value: value,
+			color: color,
+			thresholds: settings.thresholds,
+			gradient: settings.gradient
+		}
+	}
+}
+
+impl Widget for Bar {
+	fn render(&self, width: usize) -> Vec<Line> {
+		assert!(self.value >= 0.0 && self.value <= 1.0);
+		let size = width.saturating_sub(2);
+		let barsize = (self.value * size as f64) as usize;
+		let mut line = vec![Span { text: String::from("["), style: Style::Plain }];
+		for i in 0..barsize {
+			let color = if self.gradient {
+				//the middle of the segment decides, so a breakpoint on a segment border is exact
+				let thresholds = self.thresholds.unwrap_or(DEFAULT_THRESHOLDS);
+				gradient_color(&thresholds, (i as f64 + 0.5) / size as f64, color::GREEN)
+			}
+			else {
+				match self.thresholds {
+					Some(ref thresholds) => gradient_color(thresholds, self.value, self.color),
+					None => self.color
+				}
+			};
+			match line.last_mut() {
+				Some(ref mut span) if span.style == Style::Bar(color) => { span.text.push('='); continue; },
+				_ => {}
+			}
+			line.push(Span { text: String::from("="), style: Style::Bar(color) });
+		}
+		line.push(Span { text: pad_string(String::new(), size - barsize) + "]", style: Style::Plain });
+		vec![line]
+	}
+}
+
+//the load history with the percentages on the left and the time span below the right end
+pub struct Graph<'a> {
+	graph: &'a TieredGraph,
+	zoom: Zoom,
+	height: usize,
+	gradient: Option<Thresholds> //rows colored by the load they stand for
+}
+
+impl<'a> Graph<'a> {
+	pub fn new(settings: &Settings, graph: &'a TieredGraph, zoom: Zoom) -> Graph<'a> {
+		Graph {
+			graph: graph,
+			zoom: zoom,
+			height: settings.graph_height,
+			gradient: if settings.gradient { Some(settings.thresholds.unwrap_or(DEFAULT_THRESHOLDS)) } else { None }
+		}
+	}
+}
+
+impl<'a> Widget for Graph<'a> {
+	fn render(&self, _: usize) -> Vec<Line> {
+		//each row holds two steps: '.' for the lower and ':' for the upper half
+		let steps = self.height * 2;
+		let heights: Vec<usize> = self.graph.columns(self.zoom, GRAPH_WIDTH).iter()
+			.map(|value| (value * steps as f64) as usize)
+			.collect();
+		let mut lines = Vec::new();
+		for y in (0..self.height).rev() {
+			let label = pad_string(format!("{}%", y * 100 / (self.height - 1).max(1)), 5) + "|";
+			let values: String = heights.iter().map(|size| {
+				if *size < y * 2 { ' ' } else if *size < y * 2 + 1 { '.' } else { ':' }
+			}).collect();
+			let style = match self.gradient {
+				//the middle of the values this row stands for
+				Some(ref thresholds) => Style::Bar(gradient_color(thresholds, (y * 2 + 1) as f64 / steps as f64, color::GREEN)),
+				None => Style::Graph
+			};
+			lines.push(vec![
+				Span { text: label, style: Style::Plain },
+				Span { text: values, style: style }
+			]);
+		}
+		lines.push(vec![Span { text: pad_left(String::from(self.zoom.label()), GRAPH_WIDTH + 6), style: Style::Plain }]);
+		lines
+	}
+}
+
+//columns of text with a highlighted header, the last column takes what is left
+pub struct Table {
+	widths: Vec<usize>, //of all columns but the last
+	header: Vec<String>,
+	rows: Vec<Vec<String>>,
+	selected: Option<usize> //highlighted over the whole width
+}
+
+impl Table {
+	pub fn new(header: Vec<String>, widths: Vec<usize>) -> Table {
+		Table {
+			widths: widths,
+			header: header,
+			rows: Vec::new(),
+			selected: None
+		}
+	}
+
+	pub fn row(mut self, cells: Vec<String>) -> Table {
+		self.rows.push(cells);
+		self
+	}
+
+	pub fn select(mut self, index: usize) -> Table {
+		self.selected = Some(index);
+		self
+	}
+
+	fn join(&self, cells: &[String]) -> String {
+		let mut text = String::new();
+		for (index, cell) in cells.iter().enumerate() {
+			match self.widths.get(index) {
+				Some(width) => text.push_str(&pad_string(cell.clone(), *width)),
+				None => text.push_str(cell)
+			}
+		}
+		text
+	}
+}
+
+impl Widget for Table {
+	fn render(&self, width: usize) -> Vec<Line> {
+		let mut lines = vec![vec![Span { text: self.join(&self.header), style: Style::Highlight }]];
+		for (index, row) in self.rows.iter().enumerate() {
+			if self.selected == Some(index) {
+				lines.push(vec![Span { text: pad_string(self.join(row), width), style: Style::Highlight }]);
+			}
+			else {
+				lines.push(vec![Span { text: self.join(row), style: Style::Plain }]);
+			}
+		}
+		lines
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(lines: &[Line]) -> Vec<String> {
+		lines.iter().map(|line| line.iter().map(|span| span.text.as_str()).collect()).collect()
+	}
+
+	#[test]
+	fn constraints() {
+		use self::Constraint::*;
+		assert_eq!(split(80, &[Length(7), Length(42), Fill]), vec![7, 42, 31]);
+		assert_eq!(split(100, &[Percentage(25), Fill, Fill]), vec![25, 38, 37]);
+		assert_eq!(split(100, &[Percentage(60), Percentage(60)]), vec![60, 40]);
+		//what does not fit is cut off
+		assert_eq!(split(10, &[Length(7), Length(42), Fill]), vec![7, 3, 0]);
+	}
+
+	#[test]
+	fn columns_are_padded_and_cut() {
+		let layout = Layout::Columns(vec![
+			(Constraint::Length(4), Text::new().highlight("CPU 1:").into()),
+			(Constraint::Length(3), Layout::Rows(vec![Text::new().plain("a").into(), Text::new().plain("b").into()])),
+			(Constraint::Fill, Text::new().plain("rest").into())
+		]);
+		assert_eq!(text(&layout.render(9)), vec!["CPU a  re", "    b  "]);
+		assert_eq!(layout.render(9)[0][0], Span { text: String::from("CPU "), style: Style::Highlight });
+	}
+
+	#[test]
+	fn headers() {
+		assert_eq!(text(&Header::new("CPU").render(15)), vec!["===== CPU ====="]);
+		assert_eq!(text(&Header::new("CPU").render(14)), vec!["===== CPU ===="]);
+		assert_eq!(text(&Header::new("MEMORY").render(4)), vec![" MEMORY "]);
+	}
+
+	#[test]
+	fn bars() {
+		let lines = Bar::new(0.5, color::GREEN).render(12);
+		assert_eq!(text(&lines), vec!["[=====     ]"]);
+		assert_eq!(lines[0][1].style, Style::Bar(color::GREEN));
+		let thresholds = Some(Thresholds { warning: 0.5, critical: 0.8 });
+		let warning = Bar { value: 0.6, color: color::GREEN, thresholds: thresholds, gradient: false };
+		assert_eq!(warning.render(12)[0][1].style, Style::Bar(color::YELLOW));
+		let gradient = Bar { value: 0.9, color: color::GREEN, thresholds: thresholds, gradient: true };
+		let styles: Vec<(String, Style)> = gradient.render(12)[0].iter().map(|span| (span.text.clone(), span.style)).collect();
+		assert_eq!(styles, vec![
+			(String::from("["), Style::Plain),
+			(String::from("====="), Style::Bar(color::GREEN)),
+			(String::from("==="), Style::Bar(color::YELLOW)),
+			(String::from("="), Style::Bar(color::RED)),
+			(String::from(" ]"), Style::Plain)
+		]);
+	}
+
+	#[test]
+	fn graph_rows() {
+		let mut history = TieredGraph::new();
+		history.push_at(0, 0.4);
+		history.push_at(2000000000, 0.0); //finishes the slot
+		let graph = Graph { graph: &history, zoom: Zoom::Minute, height: GRAPH_HEIGHT, gradient: None };
+		let lines = text(&graph.render(80));
+		assert_eq!(lines.len(), GRAPH_HEIGHT + 1);
+		assert_eq!(&lines[0][..6], "100% |");
+		assert_eq!(&lines[4][..6], "0%   |");
+		//40 % are four steps, the row of the 50 % shows the top
+		assert!(lines[4].ends_with(':') && lines[3].ends_with(':') && lines[2].ends_with('.') && lines[1].ends_with(' '));
+		assert!(lines[5].ends_with(Zoom::Minute.label()));
+		let graph = Graph { graph: &history, zoom: Zoom::Hour, height: 3, gradient: None };
+		let labels: Vec<String> = text(&graph.render(80)).iter().map(|line| String::from(&line[..6])).collect();
+		assert_eq!(labels, vec!["100% |", "50%  |", "0%   |", "      "]);
+	}
+
+	#[test]
+	fn tables() {
+		let table = Table::new(vec![String::new(), String::from("MIN"), String::from("MAX")], vec![7, 6])
+			.row(vec![String::from("TOTAL:"), String::from("1 %"), String::from("99 %")]);
+		let lines = table.render(80);
+		assert_eq!(text(&lines), vec!["       MIN   MAX", "TOTAL: 1 %   99 %"]);
+		assert_eq!(lines[0][0].style, Style::Highlight);
+	}
+
+	#[test]
+	fn selected_row() {
+		let table = Table::new(vec![String::from("PID"), String::from("NAME")], vec![5])
+			.row(vec![String::from("1"), String::from("init")])
+			.row(vec![String::from("2"), String::from("kthreadd")])
+			.select(1);
+		let lines = table.render(16);
+		assert_eq!(text(&lines), vec!["PID  NAME", "1    init", "2    kthreadd   "]);
+		assert_eq!(lines[1][0].style, Style::Plain);
+		assert_eq!(lines[2][0].style, Style::Highlight);
+	}
+}